@@ -0,0 +1,1135 @@
+use crate::config::{
+    ChildConfig, Config, Env, InputConfig, RespawnConfig, SocketMode, StderrConfig, TimeoutConfig,
+    TransportConfig,
+};
+use crate::error::Error;
+use crate::logger;
+use crate::mux::Multiplexer;
+use crate::session::{CallOf, RequestOf, ResponseOf, ReturnOf, Session};
+use sdvxio_pipe_proto::{
+    Api, ApiCall, CallKind, Capabilities, ChildToParent, CorruptFrame, Endpoint, Hello, LogLevel,
+    Message, PROTOCOL_VERSION, ParentToChild, PreSharedKey, Receiver, ShmRegion, ShmSide, Socket,
+    image_arch,
+};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsString;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::mem::Discriminant;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Child logs kept until the game calls in again, the oldest ones are dropped beyond this.
+const MAX_QUEUED_LOGS: usize = 1024;
+
+/// The child and the state restored on it after a respawn.
+///
+/// Shared by the game's threads: requests wait for their response without holding any lock,
+/// so calls from the input and output threads can be in flight at the same time.
+pub struct Child<S: Session> {
+    config: ChildConfig,
+    transport: TransportConfig,
+    respawn: RespawnConfig,
+    timeouts: TimeoutConfig,
+    api_timeouts: S::Timeouts,
+    input_config: InputConfig,
+    /// Replaced on respawn, requests keep their own reference while waiting.
+    process: Mutex<Option<Arc<Process<S::Api>>>>,
+    /// Held for a whole respawn, so that requests failing together only respawn the child once.
+    respawning: Mutex<()>,
+    state: Mutex<ChildState<S>>,
+}
+
+struct ChildState<S: Session> {
+    capabilities: Capabilities,
+    /// Most verbose child log level forwarded, applied again after a respawn.
+    log_level: Option<LogLevel>,
+    last_known: HashMap<FallbackKey<S::Api>, ChildToParent<S::Api>>,
+    initialized: bool,
+    respawn_attempts: u32,
+    consecutive_timeouts: u32,
+    session: S,
+}
+
+/// The session of a [`Child`], locked along with the rest of its state.
+pub struct SessionGuard<'a, S: Session>(MutexGuard<'a, ChildState<S>>);
+
+/// A running child program.
+pub struct Process<A: Api> {
+    /// `None` when the program runs on its own, possibly on another machine.
+    child: Mutex<Option<std::process::Child>>,
+    /// Fed by a reader thread, which hands each response to the request waiting for it.
+    mux: Arc<Multiplexer<A>>,
+    /// Latest value pushed by the child and when it was received, cleared by the reader thread
+    /// once the pipe closes.
+    pushed: Pushed<A>,
+    /// Logs forwarded by the child, queued by the reader thread as it must not log itself.
+    logs: Arc<Mutex<VecDeque<ChildLog>>>,
+    shm: Option<ShmTransport>,
+    socket: Option<Socket>,
+    /// Kept open with the shared-memory transport, the child exits once it is closed.
+    _stdin: Option<ChildStdin>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+}
+
+/// A value pushed by the child and when it was received.
+type Pushed<A> = Arc<Mutex<Option<(<A as Api>::Push, Instant)>>>;
+
+struct ShmTransport {
+    region: Arc<ShmRegion>,
+    path: PathBuf,
+}
+
+struct ChildLog {
+    level: LogLevel,
+    target: String,
+    message: String,
+}
+
+/// Identifies the value returned by a request, for the last-known-good fallback.
+enum FallbackKey<A: Api> {
+    /// Input functions return a value depending on their arguments, such as the GPIO bank.
+    Input(A::Call),
+    /// Output functions return whether they succeeded, whatever their arguments.
+    Output(&'static str),
+    Request(Discriminant<A::Request>),
+}
+
+impl<S: Session> Child<S> {
+    /// Starts the child program described by `config`.
+    pub(crate) fn spawn(config: &Config) -> Result<Self, Error> {
+        let api_timeouts = config.api_timeouts()?;
+        let process = Process::spawn(&config.child, &config.transport, &config.timeouts)?;
+        Ok(Self {
+            process: Mutex::new(Some(Arc::new(process))),
+            config: config.child.clone(),
+            transport: config.transport.clone(),
+            respawn: config.respawn.clone(),
+            timeouts: config.timeouts.clone(),
+            api_timeouts,
+            input_config: config.input.clone(),
+            respawning: Mutex::new(()),
+            state: Mutex::new(ChildState {
+                capabilities: Capabilities::empty(),
+                log_level: config.log.forwarded_level(),
+                last_known: HashMap::new(),
+                initialized: false,
+                respawn_attempts: 0,
+                consecutive_timeouts: 0,
+                session: S::default(),
+            }),
+        })
+    }
+
+    pub(crate) fn kill(&self) {
+        self.drain_logs();
+        if let Some(process) = self.current().take() {
+            process.kill();
+        }
+    }
+
+    /// Capabilities supported by both sides.
+    pub fn capabilities(&self) -> Capabilities {
+        self.state().capabilities
+    }
+
+    pub fn input_config(&self) -> &InputConfig {
+        &self.input_config
+    }
+
+    pub fn session(&self) -> SessionGuard<'_, S> {
+        SessionGuard(self.state())
+    }
+
+    /// Calls a function on the child, respawning it if it exited or stopped responding.
+    ///
+    /// A call that times out returns the last value received for the same call instead.
+    pub fn forward(&self, call: CallOf<S>) -> Result<ReturnOf<S>, Error> {
+        self.state().session.record(&call);
+        match self.exchange_with(|_, _| Ok(ParentToChild::Call(call)))? {
+            ChildToParent::Return(value) => Ok(value),
+            _ => Err(Error::WrongResponseType),
+        }
+    }
+
+    /// Sends a request to the child, like [`Child::forward`].
+    pub fn request(&self, request: RequestOf<S>) -> Result<ResponseOf<S>, Error> {
+        self.request_with(|_, _| Ok(request.clone()))
+    }
+
+    /// Like [`Child::request`], building the request under the state lock right before sending
+    /// it, so that requests built from the session are sent in the same order.
+    pub fn request_with(
+        &self,
+        build: impl Fn(&Process<S::Api>, &mut S) -> Result<RequestOf<S>, Error>,
+    ) -> Result<ResponseOf<S>, Error> {
+        let response = self.exchange_with(|process, state| {
+            build(process, &mut state.session).map(ParentToChild::Request)
+        })?;
+        match response {
+            ChildToParent::Response(response) => Ok(response),
+            _ => Err(Error::WrongResponseType),
+        }
+    }
+
+    /// Calls a function on `process` alone, while restoring it.
+    pub fn call_once(
+        &self,
+        process: &Process<S::Api>,
+        call: CallOf<S>,
+    ) -> Result<ReturnOf<S>, Error> {
+        match self.exchange_once(process, ParentToChild::Call(call))? {
+            ChildToParent::Return(value) => Ok(value),
+            _ => Err(Error::WrongResponseType),
+        }
+    }
+
+    /// Sends a request to `process` alone, while restoring it.
+    pub fn request_once(
+        &self,
+        process: &Process<S::Api>,
+        request: RequestOf<S>,
+    ) -> Result<ResponseOf<S>, Error> {
+        match self.exchange_once(process, ParentToChild::Request(request))? {
+            ChildToParent::Response(response) => Ok(response),
+            _ => Err(Error::WrongResponseType),
+        }
+    }
+
+    /// Latest value pushed by the child and when it was received.
+    pub fn latest_push(&self) -> Option<(<S::Api as Api>::Push, Instant)> {
+        self.current()
+            .clone()
+            .and_then(|process| process.latest_push())
+    }
+
+    fn exchange_with(
+        &self,
+        build: impl Fn(&Process<S::Api>, &mut ChildState<S>) -> Result<ParentToChild<S::Api>, Error>,
+    ) -> Result<ChildToParent<S::Api>, Error> {
+        loop {
+            let process = self.process()?;
+            // Queued under the state lock, so that requests built from the session are written
+            // in order, but written by the multiplexer's thread once the lock is released
+            let (msg, ticket) = {
+                let mut state = self.state();
+                let msg = build(&process, &mut state)?;
+                let ticket = process.mux.send(msg.clone());
+                (msg, ticket)
+            };
+            let fallback_key = FallbackKey::of(&msg);
+            let result = ticket.and_then(|ticket| ticket.wait(self.deadline(&msg)));
+
+            match result {
+                Ok(response) => {
+                    let mut state = self.state();
+                    state.respawn_attempts = 0;
+                    state.consecutive_timeouts = 0;
+                    if let Some(key) = fallback_key {
+                        state.last_known.insert(key, response.clone());
+                    }
+                    return Ok(response);
+                }
+                Err(Error::Timeout { after }) => {
+                    let mut state = self.state();
+                    state.consecutive_timeouts += 1;
+                    if state.consecutive_timeouts == 1 {
+                        log::warn!("{:?} timed out after {} ms", msg, after.as_millis());
+                    }
+
+                    let respawn_after = self.timeouts.respawn_after;
+                    if respawn_after != 0 && state.consecutive_timeouts >= respawn_after {
+                        log::warn!(
+                            "The {} child did not answer {} requests in a row",
+                            S::Api::NAME,
+                            state.consecutive_timeouts
+                        );
+                        state.consecutive_timeouts = 0;
+                        drop(state);
+                        self.respawn(&process)?;
+                        continue;
+                    }
+
+                    return fallback_key
+                        .and_then(|key| state.last_known.get(&key).cloned())
+                        .ok_or(Error::Timeout { after });
+                }
+                Err(Error::CorruptFrame { discarded }) => {
+                    log::warn!(
+                        "Discarded {} corrupted bytes from the {} child while waiting for {:?}",
+                        discarded,
+                        S::Api::NAME,
+                        msg
+                    );
+                    return fallback_key
+                        .and_then(|key| self.state().last_known.get(&key).cloned())
+                        .ok_or(Error::CorruptFrame { discarded });
+                }
+                Err(Error::IoError(err)) if !is_fini(&msg) => {
+                    let Some(reason) = process.lost() else {
+                        return Err(Error::IoError(err));
+                    };
+                    log::warn!(
+                        "The {} child {} while handling {:?}",
+                        S::Api::NAME,
+                        reason,
+                        msg
+                    );
+                    self.respawn(&process)?;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Exchanges versions with the child and stores the capabilities supported by both sides.
+    pub(crate) fn handshake(&self) -> Result<Hello, Error> {
+        let process = self.process()?;
+        self.handshake_with(&process)
+    }
+
+    fn handshake_with(&self, process: &Process<S::Api>) -> Result<Hello, Error> {
+        let ours = Hello::current::<S::Api>();
+        let theirs = match self.exchange_once(process, ParentToChild::Hello(ours))? {
+            ChildToParent::HelloResponse(hello) => hello,
+            _ => return Err(Error::WrongResponseType),
+        };
+
+        if theirs.protocol_version != PROTOCOL_VERSION {
+            return Err(Error::ProtocolMismatch {
+                parent: PROTOCOL_VERSION,
+                child: theirs.protocol_version,
+            });
+        }
+        if theirs.version != ours.version {
+            return Err(Error::VersionMismatch {
+                parent: ours.version,
+                child: theirs.version,
+            });
+        }
+
+        let capabilities = ours.capabilities.intersection(theirs.capabilities);
+        let log_level = {
+            let mut state = self.state();
+            state.capabilities = capabilities;
+            state.log_level
+        };
+        if capabilities.contains(Capabilities::LOG_FORWARD) {
+            match self.exchange_once(process, ParentToChild::SetLogLevel(log_level))? {
+                ChildToParent::SetLogLevelResponse => {}
+                _ => return Err(Error::WrongResponseType),
+            }
+        }
+        Ok(theirs)
+    }
+
+    /// Re-emits the logs forwarded by the child since the last call, on the calling thread.
+    pub(crate) fn drain_logs(&self) {
+        let Some(process) = self.current().clone() else {
+            return;
+        };
+        let logs = std::mem::take(&mut *process.logs.lock().expect("failed to lock child logs"));
+        for log in logs {
+            logger::forward(log.level.into(), &log.target, &log.message);
+        }
+    }
+
+    /// Changes the most verbose child log level forwarded, or stops forwarding with `None`.
+    ///
+    /// Returns `false` if the child cannot forward its logs.
+    pub(crate) fn set_log_level(&self, level: Option<LogLevel>) -> Result<bool, Error> {
+        let capabilities = {
+            let mut state = self.state();
+            state.log_level = level;
+            state.capabilities
+        };
+        if !capabilities.contains(Capabilities::LOG_FORWARD) {
+            return Ok(false);
+        }
+        match self.exchange_with(|_, _| Ok(ParentToChild::SetLogLevel(level)))? {
+            ChildToParent::SetLogLevelResponse => Ok(true),
+            _ => Err(Error::WrongResponseType),
+        }
+    }
+
+    pub(crate) fn init(&self) -> Result<bool, Error> {
+        let process = self.process()?;
+        self.init_with(&process)
+    }
+
+    fn init_with(&self, process: &Process<S::Api>) -> Result<bool, Error> {
+        let value = self.call_once(process, S::Api::INIT)?;
+        let success = <S::Api as Api>::initialized(&value);
+        self.state().initialized = success;
+        if success {
+            S::started(self, process)?;
+        }
+        Ok(success)
+    }
+
+    fn exchange_once(
+        &self,
+        process: &Process<S::Api>,
+        msg: ParentToChild<S::Api>,
+    ) -> Result<ChildToParent<S::Api>, Error> {
+        let deadline = self.deadline(&msg);
+        process.mux.send(msg)?.wait(deadline)
+    }
+
+    fn deadline(&self, msg: &ParentToChild<S::Api>) -> Option<Duration> {
+        let ms = match msg {
+            ParentToChild::Hello(_) | ParentToChild::SetLogLevel(_) => self.timeouts.hello_ms,
+            ParentToChild::Call(call) => S::timeout(&self.timeouts, &self.api_timeouts, call),
+            ParentToChild::Request(request) => {
+                S::request_timeout(&self.timeouts, &self.api_timeouts, request)
+            }
+        };
+        (ms != 0).then(|| Duration::from_millis(ms))
+    }
+
+    /// Respawns the child after `failed` stopped working, unless another request already did.
+    fn respawn(&self, failed: &Arc<Process<S::Api>>) -> Result<(), Error> {
+        let _respawning = self.respawning.lock().expect("failed to lock respawn");
+        let current = self.current().clone();
+        if !current.is_some_and(|current| Arc::ptr_eq(&current, failed)) {
+            return Ok(());
+        }
+
+        loop {
+            let attempts = {
+                let mut state = self.state();
+                if state.respawn_attempts >= self.respawn.max_retries {
+                    let attempts = state.respawn_attempts;
+                    drop(state);
+                    self.kill();
+                    return Err(Error::RespawnFailed { attempts });
+                }
+                state.respawn_attempts += 1;
+                state.respawn_attempts
+            };
+            std::thread::sleep(self.respawn.backoff(attempts - 1));
+
+            log::warn!(
+                "Respawning the {} child (attempt {}/{})",
+                S::Api::NAME,
+                attempts,
+                self.respawn.max_retries
+            );
+            match self.restart() {
+                Ok(()) => {
+                    log::info!("The {} child respawned and state restored", S::Api::NAME);
+                    return Ok(());
+                }
+                Err(err) => log::warn!("Failed to respawn the {} child: {}", S::Api::NAME, err),
+            }
+        }
+    }
+
+    /// Starts a new child and restores the state on it before letting requests through.
+    fn restart(&self) -> Result<(), Error> {
+        self.kill();
+        let process = Arc::new(Process::spawn(
+            &self.config,
+            &self.transport,
+            &self.timeouts,
+        )?);
+        self.restore(&process).inspect_err(|_| process.kill())?;
+        *self.current() = Some(process);
+        Ok(())
+    }
+
+    fn restore(&self, process: &Process<S::Api>) -> Result<(), Error> {
+        self.handshake_with(process)?;
+
+        if !self.state().initialized {
+            return Ok(());
+        }
+        if !self.init_with(process)? {
+            return Err(Error::InitFailed);
+        }
+        S::restore(self, process)
+    }
+
+    /// The current process, waiting for a respawn in progress to finish.
+    fn process(&self) -> Result<Arc<Process<S::Api>>, Error> {
+        if let Some(process) = self.current().clone() {
+            return Ok(process);
+        }
+        drop(self.respawning.lock().expect("failed to lock respawn"));
+        self.current().clone().ok_or(Error::ChildUnavailable)
+    }
+
+    fn current(&self) -> MutexGuard<'_, Option<Arc<Process<S::Api>>>> {
+        self.process.lock().expect("failed to lock child process")
+    }
+
+    fn state(&self) -> MutexGuard<'_, ChildState<S>> {
+        self.state.lock().expect("failed to lock child state")
+    }
+}
+
+impl<S: Session> Deref for SessionGuard<'_, S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.0.session
+    }
+}
+
+impl<S: Session> DerefMut for SessionGuard<'_, S> {
+    fn deref_mut(&mut self) -> &mut S {
+        &mut self.0.session
+    }
+}
+
+impl<A: Api> Process<A> {
+    /// Starts the program and connects to it through `transport`. Relative paths are resolved
+    /// against the base directory.
+    fn spawn(
+        config: &ChildConfig,
+        transport: &TransportConfig,
+        timeouts: &TimeoutConfig,
+    ) -> Result<Self, Error> {
+        let mut threads = Vec::new();
+        let mut shm = None;
+        let mut socket = None;
+        let mut stdin = None;
+
+        let (child, writer, reader): (_, Box<dyn Write + Send>, Box<dyn Read + Send>) =
+            match transport {
+                TransportConfig::Pipe => {
+                    let mut child = start_program::<A>(config, &[], &[], true)?;
+                    let writer = Box::new(child.stdin.take().unwrap());
+                    let reader = Box::new(child.stdout.take().unwrap());
+                    (Some(child), writer, reader)
+                }
+                TransportConfig::Shm { path } => {
+                    let path = match path {
+                        Some(path) => config.resolve(path),
+                        None => std::env::temp_dir().join(format!(
+                            "{}-pipe-{}.shm",
+                            A::NAME,
+                            std::process::id()
+                        )),
+                    };
+                    let region = ShmRegion::create(&path).map_err(|err| Error::Config {
+                        message: format!("cannot create shared memory file: {}", err),
+                        path: path.clone(),
+                    })?;
+
+                    let args = [OsString::from("--shm"), path.clone().into_os_string()];
+                    let mut child = start_program::<A>(config, &args, &[], true)?;
+                    stdin = child.stdin.take();
+
+                    // The child only writes to stdout when it crashes, so wake the reader once
+                    // it closes
+                    let stdout = child.stdout.take().unwrap();
+                    let watched = region.clone();
+                    threads.push(
+                        std::thread::Builder::new()
+                            .name(format!("{}-pipe-watcher", A::NAME))
+                            .spawn(move || {
+                                let mut stdout = stdout;
+                                let _ = std::io::copy(&mut stdout, &mut std::io::sink());
+                                watched.close();
+                            })?,
+                    );
+
+                    let writer = Box::new(region.writer(ShmSide::Parent));
+                    let reader = Box::new(region.reader(ShmSide::Parent));
+                    shm = Some(ShmTransport { region, path });
+                    (Some(child), writer, reader)
+                }
+                TransportConfig::Socket {
+                    endpoint,
+                    mode,
+                    spawn,
+                    psk,
+                } => {
+                    // Given through the environment, so that it does not show in process lists
+                    let psk_name = Env::of::<A>().name("PSK");
+                    let env: &[_] = match psk {
+                        Some(psk) => &[(psk_name.as_str(), psk.as_str())],
+                        None => &[],
+                    };
+                    let (child, connected) = match mode {
+                        SocketMode::Connect => {
+                            let child = spawn
+                                .then(|| {
+                                    let args = [
+                                        "--listen".into(),
+                                        "--once".into(),
+                                        endpoint.to_string().into(),
+                                    ];
+                                    start_program::<A>(config, &args, env, false)
+                                })
+                                .transpose()?;
+                            (child, connect(endpoint, timeouts.connect()))
+                        }
+                        SocketMode::Listen => {
+                            let listener = endpoint.bind()?;
+                            let child = spawn
+                                .then(|| {
+                                    let args = ["--connect".into(), endpoint.to_string().into()];
+                                    start_program::<A>(config, &args, env, false)
+                                })
+                                .transpose()?;
+                            let connected = match timeouts.connect() {
+                                Some(timeout) => listener.accept_timeout(timeout),
+                                None => listener.accept(),
+                            };
+                            (child, connected)
+                        }
+                    };
+
+                    let connected = connected.and_then(|mut connected| {
+                        if let Some(psk) = psk {
+                            connected.set_read_timeout(timeouts.connect())?;
+                            PreSharedKey::new(psk.as_str()).authenticate_parent(&mut connected)?;
+                            connected.set_read_timeout(None)?;
+                        }
+                        let writer = connected.try_clone()?;
+                        let reader = connected.try_clone()?;
+                        Ok((connected, writer, reader))
+                    });
+                    let (connected, writer, reader) = match connected {
+                        Ok(connected) => connected,
+                        Err(err) => {
+                            if let Some(mut child) = child {
+                                let _ = child.kill();
+                                let _ = child.wait();
+                            }
+                            return Err(Error::Connect {
+                                endpoint: endpoint.clone(),
+                                error: err,
+                            });
+                        }
+                    };
+                    socket = Some(connected);
+                    (child, Box::new(writer), Box::new(reader))
+                }
+            };
+        let mux = Multiplexer::new(writer)?;
+        let mut rx = Receiver::new(reader);
+
+        // These threads are not created through the game's thread API, so they must not log.
+        let reader_mux = mux.clone();
+        let pushed = Arc::new(Mutex::new(None));
+        let reader_pushed = pushed.clone();
+        let logs = Arc::new(Mutex::new(VecDeque::new()));
+        let reader_logs = logs.clone();
+        let reader = std::thread::Builder::new()
+            .name(format!("{}-pipe-reader", A::NAME))
+            .spawn(move || {
+                loop {
+                    let response = rx.recv();
+                    match response {
+                        Ok(Message {
+                            payload: ChildToParent::Push(push),
+                            ..
+                        }) => {
+                            *reader_pushed.lock().unwrap() = Some((push, Instant::now()));
+                        }
+                        Ok(Message {
+                            payload:
+                                ChildToParent::Log {
+                                    level,
+                                    target,
+                                    message,
+                                },
+                            ..
+                        }) => {
+                            let mut logs = reader_logs.lock().unwrap();
+                            if logs.len() >= MAX_QUEUED_LOGS {
+                                logs.pop_front();
+                            }
+                            logs.push_back(ChildLog {
+                                level,
+                                target,
+                                message,
+                            });
+                        }
+                        Ok(response) => reader_mux.dispatch(response),
+                        Err(err) => match CorruptFrame::from_io(&err) {
+                            // The next frame is intact, let the requests decide what to do
+                            Some(frame) => reader_mux.corrupt(frame.discarded),
+                            None => {
+                                *reader_pushed.lock().unwrap() = None;
+                                reader_mux.close(&err);
+                                break;
+                            }
+                        },
+                    }
+                }
+            })?;
+        threads.push(reader);
+
+        Ok(Self {
+            child: Mutex::new(child),
+            mux,
+            pushed,
+            logs,
+            shm,
+            socket,
+            _stdin: stdin,
+            threads: Mutex::new(threads),
+        })
+    }
+
+    /// Stops the program and fails the requests still waiting on it.
+    fn kill(&self) {
+        if let Some(child) = &mut *self.child() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        if let Some(shm) = &self.shm {
+            shm.region.close();
+        }
+        if let Some(socket) = &self.socket {
+            let _ = socket.shutdown();
+        }
+        let threads = std::mem::take(&mut *self.threads.lock().expect("failed to lock threads"));
+        for thread in threads {
+            let _ = thread.join();
+        }
+        self.mux.disconnect();
+    }
+
+    /// Latest value streamed by the child, through pushes or the shared input block.
+    ///
+    /// A value published to the shared input block is only taken as received again once it
+    /// changes, so streamed values should carry a sequence number.
+    fn latest_push(&self) -> Option<(A::Push, Instant)> {
+        let mut pushed = self.pushed.lock().expect("failed to lock pushed value");
+        if let Some(shm) = &self.shm
+            && let Some(published) = shm.region.read_input::<A::Push>()
+            && pushed
+                .as_ref()
+                .is_none_or(|(previous, _)| *previous != published)
+        {
+            *pushed = Some((published, Instant::now()));
+        }
+        pushed.clone()
+    }
+
+    /// Publishes `value` to the shared output block, returning `false` without shared memory.
+    pub fn publish_output<T: Serialize>(&self, value: &T) -> Result<bool, Error> {
+        match &self.shm {
+            Some(shm) => {
+                shm.region.publish_output(value)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Describes why the connection failed, if the program is gone.
+    ///
+    /// Waits briefly for a local child to exit, as a broken pipe may be noticed before the exit.
+    /// A program running elsewhere is gone as soon as its socket fails.
+    fn lost(&self) -> Option<String> {
+        let mut child = self.child();
+        let Some(child) = &mut *child else {
+            return Some("disconnected".to_owned());
+        };
+        for _ in 0..10 {
+            if let Ok(Some(status)) = child.try_wait() {
+                return Some(format!("exited with {}", status));
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        None
+    }
+
+    fn child(&self) -> MutexGuard<'_, Option<std::process::Child>> {
+        self.child.lock().expect("failed to lock child process")
+    }
+}
+
+impl<A: Api> Drop for Process<A> {
+    fn drop(&mut self) {
+        // The mapping must be released before the file can be removed on Windows
+        self.mux.disconnect();
+        if let Some(shm) = self.shm.take() {
+            drop(shm.region);
+            let _ = std::fs::remove_file(shm.path);
+        }
+    }
+}
+
+/// Starts the `sdvxio-pipe-program` build matching the wrapped library, serving `A` with `args`
+/// and the library before the configured arguments and `env` after the configured environment.
+/// Its stdin and stdout are piped when `piped` is set.
+fn start_program<A: Api>(
+    config: &ChildConfig,
+    args: &[OsString],
+    env: &[(&str, &str)],
+    piped: bool,
+) -> Result<std::process::Child, Error> {
+    let library = config.library();
+    let program = select_program(config, &library)?;
+    let stderr = match &config.stderr {
+        StderrConfig::Null => Stdio::null(),
+        StderrConfig::Inherit => Stdio::inherit(),
+        StderrConfig::File(path) => {
+            let path = config.resolve(path);
+            let file = File::create(&path).map_err(|err| Error::Config {
+                message: format!("cannot create child stderr file: {}", err),
+                path,
+            })?;
+            Stdio::from(file)
+        }
+    };
+    let stdio = || if piped { Stdio::piped() } else { Stdio::null() };
+
+    Command::new(&program)
+        .arg("--api")
+        .arg(A::NAME)
+        .args(args)
+        .arg("--library")
+        .arg(&library)
+        .args(&config.args)
+        .envs(&config.env)
+        .envs(env.iter().copied())
+        .current_dir(config.working_dir())
+        .stdin(stdio())
+        .stdout(stdio())
+        .stderr(stderr)
+        .spawn()
+        .map_err(|error| Error::Spawn { program, error })
+}
+
+/// Picks the first program built for the architecture of `library`, as a process cannot load a
+/// library of another architecture.
+fn select_program(config: &ChildConfig, library: &Path) -> Result<PathBuf, Error> {
+    let arch = image_arch(library).map_err(|error| Error::Library {
+        path: library.to_owned(),
+        error,
+    })?;
+
+    let searched = config.programs(arch);
+    for program in &searched {
+        match image_arch(program) {
+            Ok(found) if found == arch => return Ok(program.clone()),
+            Ok(found) => log::warn!(
+                "Skipping {}, built for {} rather than {}",
+                program.display(),
+                found,
+                arch
+            ),
+            Err(_) => {}
+        }
+    }
+    Err(Error::NoMatchingProgram {
+        arch,
+        library: library.to_owned(),
+        searched,
+    })
+}
+
+/// Connects to `endpoint`, retrying until `timeout` as the program may still be starting.
+fn connect(endpoint: &Endpoint, timeout: Option<Duration>) -> std::io::Result<Socket> {
+    let started = Instant::now();
+    loop {
+        match endpoint.connect(timeout) {
+            Ok(socket) => return Ok(socket),
+            Err(err) if timeout.is_some_and(|timeout| started.elapsed() >= timeout) => {
+                return Err(err);
+            }
+            Err(_) => std::thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+/// Whether `msg` finalizes the library, after which the child exits on its own.
+fn is_fini<A: Api>(msg: &ParentToChild<A>) -> bool {
+    matches!(msg, ParentToChild::Call(call) if *call == A::FINI)
+}
+
+impl<A: Api> FallbackKey<A> {
+    fn of(msg: &ParentToChild<A>) -> Option<Self> {
+        match msg {
+            ParentToChild::Call(call) => match call.kind() {
+                CallKind::Input => Some(FallbackKey::Input(*call)),
+                CallKind::Output => Some(FallbackKey::Output(call.name())),
+                CallKind::Lifecycle => None,
+            },
+            ParentToChild::Request(request) => {
+                Some(FallbackKey::Request(std::mem::discriminant(request)))
+            }
+            ParentToChild::Hello(_) | ParentToChild::SetLogLevel(_) => None,
+        }
+    }
+}
+
+// Implemented by hand, as deriving would require the API itself to be comparable
+impl<A: Api> PartialEq for FallbackKey<A> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (FallbackKey::Input(call), FallbackKey::Input(other)) => call == other,
+            (FallbackKey::Output(name), FallbackKey::Output(other)) => name == other,
+            (FallbackKey::Request(request), FallbackKey::Request(other)) => request == other,
+            _ => false,
+        }
+    }
+}
+
+impl<A: Api> Eq for FallbackKey<A> {}
+
+impl<A: Api> Hash for FallbackKey<A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            FallbackKey::Input(call) => call.hash(state),
+            FallbackKey::Output(name) => name.hash(state),
+            FallbackKey::Request(request) => request.hash(state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::NoTimeouts;
+    use sdvxio_pipe_proto::sdvxio::{Call, Return, Sdvxio};
+    use sdvxio_pipe_proto::{Arch, Sender};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Replays the GPIO lights last set, like the `sdvxio` proxy does.
+    #[derive(Default)]
+    struct Lights {
+        gpio_lights: Option<u32>,
+    }
+
+    impl Session for Lights {
+        type Api = Sdvxio;
+        type Timeouts = NoTimeouts;
+
+        fn record(&mut self, call: &Call) {
+            if let Call::SetGpioLights { gpio_lights } = *call {
+                self.gpio_lights = Some(gpio_lights);
+            }
+        }
+
+        fn restore(child: &Child<Self>, process: &Process<Sdvxio>) -> Result<(), Error> {
+            let gpio_lights = child.session().gpio_lights;
+            if let Some(gpio_lights) = gpio_lights {
+                child.call_once(process, Call::SetGpioLights { gpio_lights })?;
+            }
+            Ok(())
+        }
+    }
+
+    /// A program connecting to the parent again whenever it is dropped. Each connection
+    /// answers `GetSpinnerPos` with its number times 100, plus the spinner, unless stalled.
+    #[derive(Clone, Default)]
+    struct Program {
+        /// Cleared by every new connection, as a respawned program answers again.
+        stalled: Arc<AtomicBool>,
+        /// Calls received by each connection.
+        calls: Arc<Mutex<Vec<Vec<Call>>>>,
+    }
+
+    impl Program {
+        fn run(&self, address: String) {
+            let program = self.clone();
+            std::thread::spawn(move || {
+                // Ends once the parent stops listening for a while
+                let mut deadline = Instant::now() + Duration::from_secs(5);
+                while Instant::now() < deadline {
+                    match TcpStream::connect(&address) {
+                        Ok(stream) => {
+                            program.serve(stream);
+                            deadline = Instant::now() + Duration::from_secs(5);
+                        }
+                        Err(_) => std::thread::sleep(Duration::from_millis(10)),
+                    }
+                }
+            });
+        }
+
+        fn serve(&self, stream: TcpStream) {
+            self.stalled.store(false, Ordering::Release);
+            let connection = {
+                let mut calls = self.calls.lock().unwrap();
+                calls.push(Vec::new());
+                calls.len() - 1
+            };
+            let mut rx =
+                Receiver::<_, Message<ParentToChild<Sdvxio>>>::new(stream.try_clone().unwrap());
+            let mut tx = Sender::new(stream);
+            while let Ok(msg) = rx.recv() {
+                let response: ChildToParent<Sdvxio> = match &msg.payload {
+                    ParentToChild::Hello(_) => {
+                        ChildToParent::HelloResponse(Hello::current::<Sdvxio>())
+                    }
+                    ParentToChild::SetLogLevel(_) => ChildToParent::SetLogLevelResponse,
+                    ParentToChild::Call(call) => {
+                        self.calls.lock().unwrap()[connection].push(*call);
+                        match *call {
+                            Call::Init {} => ChildToParent::Return(Return::Init(true)),
+                            Call::SetGpioLights { .. } => {
+                                ChildToParent::Return(Return::SetGpioLights(()))
+                            }
+                            Call::GetSpinnerPos { spinner_no } => {
+                                if self.stalled.load(Ordering::Acquire) {
+                                    continue;
+                                }
+                                let pos = connection as u16 * 100 + u16::from(spinner_no);
+                                ChildToParent::Return(Return::GetSpinnerPos(pos))
+                            }
+                            _ => continue,
+                        }
+                    }
+                    ParentToChild::Request(_) => continue,
+                };
+                if tx.send(&msg.reply(response)).is_err() {
+                    break;
+                }
+            }
+        }
+
+        fn calls(&self) -> Vec<Vec<Call>> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    /// Starts a child waiting for `program` on a local port, with short deadlines.
+    fn child(program: &Program, respawn_after: u32) -> Child<Lights> {
+        let address = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let mut config = Config::default();
+        config.transport = TransportConfig::Socket {
+            endpoint: Endpoint::Tcp(address.clone()),
+            mode: SocketMode::Listen,
+            spawn: false,
+            psk: None,
+        };
+        config.timeouts.call_ms = 50;
+        config.timeouts.respawn_after = respawn_after;
+        config.respawn.backoff_ms = 0;
+        program.run(address);
+
+        let child = Child::spawn(&config).unwrap();
+        child.handshake().unwrap();
+        assert!(child.init().unwrap());
+        child
+    }
+
+    fn spinner_pos(spinner_no: u8) -> Call {
+        Call::GetSpinnerPos { spinner_no }
+    }
+
+    #[test]
+    fn answers_a_timed_out_call_with_its_last_known_value() {
+        let program = Program::default();
+        let child = child(&program, 0);
+        assert_eq!(
+            child.forward(spinner_pos(0)).unwrap(),
+            Return::GetSpinnerPos(0)
+        );
+
+        program.stalled.store(true, Ordering::Release);
+        for _ in 0..3 {
+            assert_eq!(
+                child.forward(spinner_pos(0)).unwrap(),
+                Return::GetSpinnerPos(0)
+            );
+        }
+        // Another spinner has no value to fall back on
+        assert!(matches!(
+            child.forward(spinner_pos(1)),
+            Err(Error::Timeout { .. })
+        ));
+        assert_eq!(program.calls().len(), 1);
+        child.kill();
+    }
+
+    #[test]
+    fn respawns_after_timeouts_in_a_row_and_restores_the_state() {
+        let program = Program::default();
+        let child = child(&program, 2);
+        child
+            .forward(Call::SetGpioLights { gpio_lights: 0x5 })
+            .unwrap();
+
+        program.stalled.store(true, Ordering::Release);
+        assert!(matches!(
+            child.forward(spinner_pos(0)),
+            Err(Error::Timeout { .. })
+        ));
+        // The second timeout in a row respawns the program, which answers the call again
+        assert_eq!(
+            child.forward(spinner_pos(0)).unwrap(),
+            Return::GetSpinnerPos(100)
+        );
+        assert_eq!(
+            program.calls(),
+            [
+                vec![
+                    Call::Init {},
+                    Call::SetGpioLights { gpio_lights: 0x5 },
+                    spinner_pos(0),
+                    spinner_pos(0),
+                ],
+                vec![
+                    Call::Init {},
+                    Call::SetGpioLights { gpio_lights: 0x5 },
+                    spinner_pos(0),
+                ],
+            ]
+        );
+        child.kill();
+    }
+
+    /// Just enough of an ELF image to tell its architecture.
+    fn elf(machine: u16) -> Vec<u8> {
+        let mut data = vec![0; 20];
+        data[..4].copy_from_slice(b"\x7fELF");
+        data[5] = 1;
+        data[18..].copy_from_slice(&machine.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn selects_the_program_built_for_the_library() {
+        const X86: u16 = 3;
+        const X86_64: u16 = 62;
+
+        let base_dir = std::env::temp_dir().join(format!("bt5-pipe-select-{}", std::process::id()));
+        let config = ChildConfig {
+            api: "sdvxio",
+            base_dir: base_dir.clone(),
+            ..ChildConfig::default()
+        };
+        let library = config.library();
+        let [preferred, fallback] = <[PathBuf; 2]>::try_from(config.programs(Arch::X86)).unwrap();
+        std::fs::create_dir_all(preferred.parent().unwrap()).unwrap();
+        std::fs::write(&library, elf(X86)).unwrap();
+
+        // A build of another architecture is skipped
+        std::fs::write(&fallback, elf(X86_64)).unwrap();
+        assert!(matches!(
+            select_program(&config, &library),
+            Err(Error::NoMatchingProgram {
+                arch: Arch::X86,
+                ..
+            })
+        ));
+        std::fs::write(&preferred, elf(X86)).unwrap();
+        assert_eq!(select_program(&config, &library).unwrap(), preferred);
+        std::fs::write(&library, elf(X86_64)).unwrap();
+        assert_eq!(select_program(&config, &library).unwrap(), fallback);
+
+        std::fs::remove_dir_all(base_dir).unwrap();
+    }
+}
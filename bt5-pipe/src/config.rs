@@ -0,0 +1,621 @@
+use crate::error::Error;
+use crate::location;
+use sdvxio_pipe_proto::{Api, ApiCall, Arch, CallKind, Endpoint, LogLevel};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX, EXE_SUFFIX};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Default directory holding the wrapped library and one `sdvxio-pipe-program` build per
+/// architecture, in `x86` and `x64` subdirectories.
+pub const PIPE_DIR: &str = "pipe";
+
+/// Name of `sdvxio-pipe-program`, without the platform's executable suffix.
+pub const PROGRAM_NAME: &str = "sdvxio-pipe-program";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub child: ChildConfig,
+    pub transport: TransportConfig,
+    pub respawn: RespawnConfig,
+    pub timeouts: TimeoutConfig,
+    pub input: InputConfig,
+    pub log: LogConfig,
+    /// The `[timeouts.<api>]` table, read by the API's session, see [`Config::api_timeouts`].
+    #[serde(skip)]
+    api_timeouts: toml::Table,
+    /// The configuration file, which may not exist.
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChildConfig {
+    /// Name of the bridged API, passed to the program.
+    #[serde(skip)]
+    pub api: &'static str,
+    /// Directory relative paths are resolved against. Defaults to the directory of this library,
+    /// as the game may be started from anywhere.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+    /// Directory holding the programs and the wrapped library, relative to the base directory.
+    pub pipe_dir: PathBuf,
+    /// Path to `sdvxio-pipe-program`, relative to the base directory. Defaults to the build
+    /// matching the architecture of the library in the pipe directory.
+    pub program: Option<PathBuf>,
+    /// Library wrapped by the child, relative to the base directory. Defaults to the library named
+    /// after the API, such as `sdvxio.dll` or `libsdvxio.so`, in the pipe directory.
+    pub library: Option<PathBuf>,
+    /// Working directory of the child, relative to the base directory. Defaults to the pipe
+    /// directory.
+    pub working_dir: Option<PathBuf>,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub stderr: StderrConfig,
+}
+
+impl Default for ChildConfig {
+    fn default() -> Self {
+        Self {
+            api: "",
+            base_dir: PathBuf::new(),
+            pipe_dir: PathBuf::from(PIPE_DIR),
+            program: None,
+            library: None,
+            working_dir: None,
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            stderr: StderrConfig::Null,
+        }
+    }
+}
+
+impl ChildConfig {
+    /// Resolves `path` against the base directory.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.base_dir.join(path)
+    }
+
+    pub fn pipe_dir(&self) -> PathBuf {
+        self.resolve(&self.pipe_dir)
+    }
+
+    pub fn library(&self) -> PathBuf {
+        match &self.library {
+            Some(library) => self.resolve(library),
+            None => self
+                .pipe_dir()
+                .join(format!("{}{}{}", DLL_PREFIX, self.api, DLL_SUFFIX)),
+        }
+    }
+
+    pub fn working_dir(&self) -> PathBuf {
+        match &self.working_dir {
+            Some(working_dir) => self.resolve(working_dir),
+            None => self.pipe_dir(),
+        }
+    }
+
+    /// Programs that can wrap a library built for `arch`, in order of preference. Without a
+    /// configured program, the build of the architecture's subdirectory is preferred over one
+    /// installed directly in the pipe directory.
+    pub fn programs(&self, arch: Arch) -> Vec<PathBuf> {
+        match &self.program {
+            Some(program) => vec![self.resolve(program)],
+            None => {
+                let pipe_dir = self.pipe_dir();
+                let name = format!("{}{}", PROGRAM_NAME, EXE_SUFFIX);
+                vec![
+                    pipe_dir.join(arch.to_string()).join(&name),
+                    pipe_dir.join(name),
+                ]
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StderrConfig {
+    #[default]
+    Null,
+    Inherit,
+    File(PathBuf),
+}
+
+/// How messages are exchanged with the child.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum TransportConfig {
+    /// The child's stdin and stdout.
+    #[default]
+    Pipe,
+    /// A memory-mapped file, relative to the base directory. Defaults to a file in the
+    /// temporary directory.
+    Shm { path: Option<PathBuf> },
+    /// A TCP or Unix domain socket, for a program that may run on another machine.
+    Socket {
+        /// `tcp:<host>:<port>` or `unix:<path>`.
+        endpoint: Endpoint,
+        #[serde(default)]
+        mode: SocketMode,
+        /// Also starts the program locally, instead of expecting it to be run separately.
+        #[serde(default)]
+        spawn: bool,
+        /// Secret both sides must prove they know before exchanging messages.
+        #[serde(default)]
+        psk: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SocketMode {
+    /// Connects to a program listening on the endpoint.
+    #[default]
+    Connect,
+    /// Waits for the program to connect to the endpoint.
+    Listen,
+}
+
+/// How the child is restarted after it exits unexpectedly.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RespawnConfig {
+    /// Respawn attempts allowed before giving up, reset once a request succeeds again.
+    pub max_retries: u32,
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RespawnConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            backoff_ms: 100,
+            max_backoff_ms: 2000,
+        }
+    }
+}
+
+impl RespawnConfig {
+    /// Delay before the given attempt, doubling after each failed one.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.backoff_ms.saturating_mul(1 << attempt.min(16));
+        Duration::from_millis(delay.min(self.max_backoff_ms))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// Makes the child poll input on its own thread and push it, instead of being asked.
+    pub streaming: bool,
+    pub rate_hz: u16,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            streaming: false,
+            rate_hz: 1000,
+        }
+    }
+}
+
+/// How the child's logs reach the game's loggers.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Forwards the child's logs, which otherwise only go to its own log file.
+    pub forward: bool,
+    /// Most verbose level forwarded.
+    pub level: LogLevel,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            forward: true,
+            level: LogLevel::Info,
+        }
+    }
+}
+
+impl LogConfig {
+    /// Level to ask the child for, `None` when not forwarding.
+    pub fn forwarded_level(&self) -> Option<LogLevel> {
+        self.forward.then_some(self.level)
+    }
+}
+
+/// Deadlines in milliseconds, `0` waiting forever. The deadlines of an API's own functions and
+/// requests are in its `[timeouts.<api>]` table, see [`Session::Timeouts`].
+///
+/// [`Session::Timeouts`]: crate::Session::Timeouts
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutConfig {
+    /// Applies to connecting to, or waiting for, a program over a socket.
+    pub connect_ms: u64,
+    pub hello_ms: u64,
+    pub init_ms: u64,
+    pub finalize_ms: u64,
+    /// Applies to the functions of the API without a deadline of their own.
+    pub call_ms: u64,
+    /// Applies to the requests of the API without a deadline of their own.
+    pub request_ms: u64,
+    /// Consecutive timeouts after which the child is restarted, `0` never restarts it.
+    pub respawn_after: u32,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            connect_ms: 5000,
+            hello_ms: 5000,
+            init_ms: 30000,
+            finalize_ms: 5000,
+            call_ms: 100,
+            request_ms: 100,
+            respawn_after: 30,
+        }
+    }
+}
+
+impl TimeoutConfig {
+    pub fn connect(&self) -> Option<Duration> {
+        (self.connect_ms != 0).then(|| Duration::from_millis(self.connect_ms))
+    }
+
+    /// Deadline of a function of `A`, in milliseconds, for functions without one of their own.
+    pub fn call<A: Api>(&self, call: &A::Call) -> u64 {
+        if *call == A::FINI {
+            return self.finalize_ms;
+        }
+        match call.kind() {
+            CallKind::Lifecycle => self.init_ms,
+            CallKind::Input | CallKind::Output => self.call_ms,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the `<api>-pipe.toml` configuration file of the base directory if present, then
+    /// applies `<API>_PIPE_*` environment overrides, such as `SDVXIO_PIPE_DIR` for `sdvxio`.
+    ///
+    /// `<API>_PIPE_CONFIG` may point to another configuration file, which must then exist.
+    pub fn load<A: Api>() -> Result<Self, Error> {
+        let env = Env::of::<A>();
+        let base_dir = base_dir(&env)?;
+        let (path, required) = match env.var_os("CONFIG") {
+            Some(path) => (base_dir.join(path), true),
+            None => (base_dir.join(format!("{}-pipe.toml", A::NAME)), false),
+        };
+
+        let mut config = if required || path.exists() {
+            let content = std::fs::read_to_string(&path).map_err(|err| Error::Config {
+                path: path.clone(),
+                message: err.to_string(),
+            })?;
+            let config = Config::parse::<A>(&content).map_err(|message| Error::Config {
+                path: path.clone(),
+                message,
+            })?;
+            log::info!("Loaded configuration from {}", path.display());
+            config
+        } else {
+            Config::default()
+        };
+        config.path = path;
+
+        config.child.api = A::NAME;
+        config.child.base_dir = base_dir;
+        config.apply_env(&env)?;
+        Ok(config)
+    }
+
+    fn parse<A: Api>(content: &str) -> Result<Self, String> {
+        let mut table: toml::Table = toml::from_str(content).map_err(|err| err.to_string())?;
+        // Taken out first, as only the API's session knows its fields
+        let api_timeouts = match table.get_mut("timeouts") {
+            Some(toml::Value::Table(timeouts)) => timeouts.remove(A::NAME),
+            _ => None,
+        };
+        let mut config: Config = table.try_into().map_err(|err| err.to_string())?;
+        config.api_timeouts = match api_timeouts {
+            Some(toml::Value::Table(api_timeouts)) => api_timeouts,
+            Some(_) => return Err(format!("timeouts.{} must be a table", A::NAME)),
+            None => toml::Table::new(),
+        };
+        Ok(config)
+    }
+
+    /// Reads the `[timeouts.<api>]` table into the deadlines of the API's session.
+    pub fn api_timeouts<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        self.api_timeouts
+            .clone()
+            .try_into()
+            .map_err(|err: toml::de::Error| Error::Config {
+                path: self.path.clone(),
+                message: err.to_string(),
+            })
+    }
+
+    fn apply_env(&mut self, env: &Env) -> Result<(), Error> {
+        if let Some(pipe_dir) = env.var_os("DIR") {
+            self.child.pipe_dir = PathBuf::from(pipe_dir);
+        }
+        if let Some(program) = env.var_os("PROGRAM") {
+            self.child.program = Some(PathBuf::from(program));
+        }
+        if let Some(library) = env.var_os("LIBRARY") {
+            self.child.library = Some(PathBuf::from(library));
+        }
+        if let Some(working_dir) = env.var_os("WORKING_DIR") {
+            self.child.working_dir = Some(PathBuf::from(working_dir));
+        }
+        if let Some(args) = env.var("ARGS")? {
+            self.child.args = args.split_whitespace().map(str::to_owned).collect();
+        }
+        if let Some(value) = env.var("ENV")? {
+            for pair in value.split(';').filter(|pair| !pair.is_empty()) {
+                let Some((key, value)) = pair.split_once('=') else {
+                    return Err(env.invalid("ENV", value));
+                };
+                self.child.env.insert(key.to_owned(), value.to_owned());
+            }
+        }
+        if let Some(stderr) = env.var("STDERR")? {
+            self.child.stderr = match stderr.as_str() {
+                "null" => StderrConfig::Null,
+                "inherit" => StderrConfig::Inherit,
+                _ => match stderr.strip_prefix("file:") {
+                    Some(path) => StderrConfig::File(PathBuf::from(path)),
+                    None => return Err(env.invalid("STDERR", stderr)),
+                },
+            };
+        }
+        if let Some(transport) = env.var("TRANSPORT")? {
+            self.transport = match transport.as_str() {
+                "pipe" => TransportConfig::Pipe,
+                "shm" => TransportConfig::Shm { path: None },
+                _ => {
+                    let invalid = || env.invalid("TRANSPORT", transport.clone());
+                    match transport.split_once(':').ok_or_else(invalid)? {
+                        ("shm", path) => TransportConfig::Shm {
+                            path: Some(PathBuf::from(path)),
+                        },
+                        ("connect", endpoint) => TransportConfig::Socket {
+                            endpoint: endpoint.parse().map_err(|_| invalid())?,
+                            mode: SocketMode::Connect,
+                            spawn: false,
+                            psk: None,
+                        },
+                        ("listen", endpoint) => TransportConfig::Socket {
+                            endpoint: endpoint.parse().map_err(|_| invalid())?,
+                            mode: SocketMode::Listen,
+                            spawn: false,
+                            psk: None,
+                        },
+                        _ => return Err(invalid()),
+                    }
+                }
+            };
+        }
+        if let Some(value) = env.var("PSK")? {
+            match &mut self.transport {
+                TransportConfig::Socket { psk, .. } => *psk = Some(value),
+                _ => log::warn!("{} is only used by the socket transport", env.name("PSK")),
+            }
+        }
+        if let Some(level) = env.var("LOG_LEVEL")? {
+            let forwarded = match level.as_str() {
+                "off" => None,
+                "error" => Some(LogLevel::Error),
+                "warn" => Some(LogLevel::Warn),
+                "info" => Some(LogLevel::Info),
+                "debug" => Some(LogLevel::Debug),
+                "trace" => Some(LogLevel::Trace),
+                _ => return Err(env.invalid("LOG_LEVEL", level)),
+            };
+            self.log.forward = forwarded.is_some();
+            if let Some(forwarded) = forwarded {
+                self.log.level = forwarded;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Environment variables of one API, all prefixed with `<API>_PIPE_`.
+pub(crate) struct Env {
+    prefix: String,
+}
+
+impl Env {
+    pub(crate) fn of<A: Api>() -> Self {
+        Self {
+            prefix: format!("{}_PIPE_", A::NAME.to_ascii_uppercase()),
+        }
+    }
+
+    pub(crate) fn name(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    fn var_os(&self, name: &str) -> Option<OsString> {
+        std::env::var_os(self.name(name))
+    }
+
+    fn var(&self, name: &str) -> Result<Option<String>, Error> {
+        match std::env::var(self.name(name)) {
+            Ok(value) => Ok(Some(value)),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(std::env::VarError::NotUnicode(value)) => {
+                Err(self.invalid(name, value.to_string_lossy().into_owned()))
+            }
+        }
+    }
+
+    fn invalid(&self, name: &str, value: String) -> Error {
+        Error::InvalidEnv {
+            name: self.name(name),
+            value,
+        }
+    }
+}
+
+/// Directory of this library, unless overridden by `<API>_PIPE_BASE_DIR`. Falls back to the
+/// working directory if the library cannot be located.
+fn base_dir(env: &Env) -> Result<PathBuf, Error> {
+    if let Some(base_dir) = env.var_os("BASE_DIR") {
+        return Ok(PathBuf::from(base_dir));
+    }
+    match location::module_dir() {
+        Ok(module_dir) => Ok(module_dir),
+        Err(err) => {
+            log::warn!(
+                "Cannot locate the proxy library, resolving paths against the working directory: {}",
+                err
+            );
+            Ok(std::env::current_dir()?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdvxio_pipe_proto::eamio::Eamio;
+    use sdvxio_pipe_proto::sdvxio::Sdvxio;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Timeouts {
+        get_input_ms: u64,
+    }
+
+    const CONTENT: &str = "[timeouts]\ncall_ms = 50\n\n[timeouts.sdvxio]\nget_input_ms = 20\n";
+
+    #[test]
+    fn hands_the_api_timeouts_to_the_session() {
+        let config = Config::parse::<Sdvxio>(CONTENT).unwrap();
+        assert_eq!(config.timeouts.call_ms, 50);
+        assert_eq!(config.api_timeouts::<Timeouts>().unwrap().get_input_ms, 20);
+    }
+
+    #[test]
+    fn rejects_the_timeouts_of_another_api() {
+        let err = Config::parse::<Eamio>(CONTENT).unwrap_err();
+        assert!(err.contains("sdvxio"), "{err}");
+        let err = Config::parse::<Sdvxio>("[timeouts]\nsdvxio = 20\n").unwrap_err();
+        assert_eq!(err, "timeouts.sdvxio must be a table");
+    }
+
+    /// Sets `vars` under a prefix of the test's own, as tests run in parallel.
+    fn env(prefix: &str, vars: &[(&str, &str)]) -> Env {
+        let env = Env {
+            prefix: format!("{}_PIPE_", prefix),
+        };
+        for (name, value) in vars {
+            unsafe { std::env::set_var(env.name(name), value) };
+        }
+        env
+    }
+
+    #[test]
+    fn applies_environment_overrides() {
+        let env = env(
+            "OVERRIDES",
+            &[
+                ("PROGRAM", "bin/program"),
+                ("WORKING_DIR", "work"),
+                ("ARGS", " --verbose  --once "),
+                ("ENV", "A=1;B=x=y;"),
+                ("STDERR", "file:stderr.log"),
+                ("TRANSPORT", "listen:tcp:127.0.0.1:4000"),
+                ("PSK", "secret"),
+                ("LOG_LEVEL", "off"),
+            ],
+        );
+        let mut config = Config::default();
+        config.apply_env(&env).unwrap();
+
+        assert_eq!(config.child.program, Some(PathBuf::from("bin/program")));
+        assert_eq!(config.child.working_dir, Some(PathBuf::from("work")));
+        assert_eq!(config.child.args, ["--verbose", "--once"]);
+        assert_eq!(
+            config.child.env,
+            BTreeMap::from([
+                ("A".to_owned(), "1".to_owned()),
+                ("B".to_owned(), "x=y".to_owned())
+            ])
+        );
+        assert!(matches!(
+            config.child.stderr,
+            StderrConfig::File(path) if path == Path::new("stderr.log")
+        ));
+        assert!(matches!(
+            config.transport,
+            TransportConfig::Socket {
+                mode: SocketMode::Listen,
+                psk: Some(psk),
+                ..
+            } if psk == "secret"
+        ));
+        assert_eq!(config.log.forwarded_level(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_environment_overrides() {
+        for (name, value) in [
+            ("ENV", "A"),
+            ("STDERR", "console"),
+            ("TRANSPORT", "serial"),
+            ("LOG_LEVEL", "verbose"),
+        ] {
+            let env = env(&format!("INVALID_{}", name), &[(name, value)]);
+            let err = Config::default().apply_env(&env).unwrap_err();
+            assert!(
+                matches!(&err, Error::InvalidEnv { name: invalid, .. } if *invalid == env.name(name)),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn resolves_paths_against_the_base_directory() {
+        let overridden = env("BASE_DIR_OVERRIDE", &[("BASE_DIR", "game")]);
+        assert_eq!(base_dir(&overridden).unwrap(), Path::new("game"));
+        // Otherwise the directory of the module, which is the test executable here
+        let exe = std::env::current_exe().unwrap();
+        let unset = env("BASE_DIR_DEFAULT", &[]);
+        assert_eq!(base_dir(&unset).unwrap(), exe.parent().unwrap());
+
+        let base_dir = std::env::temp_dir().join("game");
+        let mut config = ChildConfig {
+            api: "sdvxio",
+            base_dir: base_dir.clone(),
+            ..ChildConfig::default()
+        };
+        let pipe_dir = base_dir.join(PIPE_DIR);
+        assert_eq!(config.pipe_dir(), pipe_dir);
+        assert_eq!(config.working_dir(), pipe_dir);
+        assert_eq!(
+            config.library(),
+            pipe_dir.join(format!("{}sdvxio{}", DLL_PREFIX, DLL_SUFFIX))
+        );
+
+        let elsewhere = std::env::temp_dir().join("elsewhere");
+        config.working_dir = Some(elsewhere.clone());
+        config.library = Some(PathBuf::from("lib/io.dll"));
+        assert_eq!(config.working_dir(), elsewhere);
+        assert_eq!(config.library(), base_dir.join("lib/io.dll"));
+    }
+}
@@ -0,0 +1,154 @@
+use sdvxio_pipe_proto::{Arch, ChildErrorKind, Endpoint, Version};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    WrongResponseType,
+    ProtocolMismatch {
+        parent: u16,
+        child: u16,
+    },
+    VersionMismatch {
+        parent: Version,
+        child: Version,
+    },
+    Config {
+        path: PathBuf,
+        message: String,
+    },
+    InvalidEnv {
+        name: String,
+        value: String,
+    },
+    Spawn {
+        program: PathBuf,
+        error: std::io::Error,
+    },
+    /// The wrapped library could not be read to find its architecture.
+    Library {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// No installed program has the architecture of the wrapped library.
+    NoMatchingProgram {
+        arch: Arch,
+        library: PathBuf,
+        searched: Vec<PathBuf>,
+    },
+    Connect {
+        endpoint: Endpoint,
+        error: std::io::Error,
+    },
+    InitFailed,
+    /// The API was not initialized, or its initialization failed.
+    NotInitialized,
+    RespawnFailed {
+        attempts: u32,
+    },
+    ChildUnavailable,
+    Timeout {
+        after: Duration,
+    },
+    CorruptFrame {
+        discarded: usize,
+    },
+    /// The child panicked while handling the request.
+    ChildPanicked {
+        message: String,
+    },
+    /// The child could not handle the request.
+    ChildFailed {
+        message: String,
+    },
+}
+
+impl Error {
+    /// Converts an `Error` response of the child.
+    pub fn from_child(kind: ChildErrorKind, message: String) -> Self {
+        match kind {
+            ChildErrorKind::Panicked => Error::ChildPanicked { message },
+            ChildErrorKind::Failed => Error::ChildFailed { message },
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::IoError(err)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IoError(err) => write!(f, "IO error: {}", err),
+            Error::WrongResponseType => write!(f, "Wrong response type"),
+            Error::ProtocolMismatch { parent, child } => {
+                write!(
+                    f,
+                    "Protocol version mismatch: parent uses {}, child uses {}",
+                    parent, child
+                )
+            }
+            Error::VersionMismatch { parent, child } => {
+                write!(
+                    f,
+                    "Version mismatch: parent is {}, child is {}",
+                    parent, child
+                )
+            }
+            Error::Config { path, message } => {
+                write!(f, "Invalid configuration {}: {}", path.display(), message)
+            }
+            Error::InvalidEnv { name, value } => {
+                write!(f, "Invalid value for {}: {:?}", name, value)
+            }
+            Error::Spawn { program, error } => {
+                write!(f, "Failed to start {}: {}", program.display(), error)
+            }
+            Error::Library { path, error } => {
+                write!(f, "Cannot read library {}: {}", path.display(), error)
+            }
+            Error::NoMatchingProgram {
+                arch,
+                library,
+                searched,
+            } => {
+                let searched = searched
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>();
+                write!(
+                    f,
+                    "No {} build of the child program is installed to wrap {}, expected one of {}",
+                    arch,
+                    library.display(),
+                    searched.join(", ")
+                )
+            }
+            Error::Connect { endpoint, error } => {
+                write!(f, "Failed to connect to {}: {}", endpoint, error)
+            }
+            Error::InitFailed => write!(f, "Child library failed to initialize"),
+            Error::NotInitialized => write!(f, "Not initialized"),
+            Error::RespawnFailed { attempts } => {
+                write!(
+                    f,
+                    "Child process could not be respawned after {} attempts",
+                    attempts
+                )
+            }
+            Error::ChildUnavailable => write!(f, "Child process is not running"),
+            Error::Timeout { after } => {
+                write!(f, "Request timed out after {} ms", after.as_millis())
+            }
+            Error::CorruptFrame { discarded } => {
+                write!(f, "Corrupted response, {} bytes discarded", discarded)
+            }
+            Error::ChildPanicked { message } => write!(f, "Child panicked: {}", message),
+            Error::ChildFailed { message } => write!(f, "Child failed: {}", message),
+        }
+    }
+}
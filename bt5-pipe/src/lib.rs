@@ -0,0 +1,282 @@
+//! Proxies forwarding a BT5 IO API, such as `sdvxio`, to `sdvxio-pipe-program`.
+//!
+//! A proxy is a library the game loads in place of the wrapped one. It defines its [`Session`],
+//! keeps a [`Host`] in a static and generates its exports from the API's function table with
+//! [`exports!`].
+
+use crate::config::Config;
+use crate::glue::log_formatter_t;
+use crate::logger::BT5Logger;
+use sdvxio_pipe_proto::{Api, LogLevel};
+use std::sync::RwLock;
+
+mod child;
+mod config;
+mod error;
+mod location;
+mod logger;
+mod mux;
+mod session;
+
+pub use child::{Child, Process, SessionGuard};
+pub use config::{InputConfig, TimeoutConfig};
+pub use error::Error;
+#[doc(hidden)]
+pub use log;
+pub use session::{CallOf, NoTimeouts, RequestOf, ResponseOf, ReturnOf, Session};
+
+pub mod glue {
+    #![allow(non_upper_case_globals)]
+    #![allow(non_camel_case_types)]
+    #![allow(non_snake_case)]
+    #![allow(unused)]
+
+    include!(concat!(env!("OUT_DIR"), "/glue.rs"));
+}
+
+/// The child of a proxy, from its API's `init` to its `fini`.
+pub struct Host<S: Session> {
+    /// Only written by `init` and `fini`, so that the game's threads can call in concurrently.
+    child: RwLock<Option<Child<S>>>,
+}
+
+impl<S: Session> Host<S> {
+    pub const fn new() -> Self {
+        Self {
+            child: RwLock::new(None),
+        }
+    }
+
+    /// Logs through the game's loggers, as the API's `set_loggers` asks.
+    pub fn set_loggers(
+        &self,
+        misc: log_formatter_t,
+        info: log_formatter_t,
+        warning: log_formatter_t,
+        fatal: log_formatter_t,
+    ) {
+        BT5Logger {
+            module: format!("{}-pipe", S::Api::NAME),
+            misc,
+            info,
+            warning,
+            fatal,
+        }
+        .install()
+        .map(|_| log::set_max_level(log::LevelFilter::Info))
+        .unwrap();
+        panic_log::initialize_hook(panic_log::Configuration::default());
+    }
+
+    /// Starts the child and initializes the library on it.
+    pub fn init(&self) -> bool {
+        let name = S::Api::NAME;
+        let config = match Config::load::<S::Api>() {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("Failed to load {}-pipe configuration: {}", name, err);
+                return false;
+            }
+        };
+
+        let child = match Child::spawn(&config) {
+            Ok(child) => child,
+            Err(err) => {
+                log::error!("Failed to start child {} process: {}", name, err);
+                return false;
+            }
+        };
+
+        log::info!("Child {} process started", name);
+
+        let mut current = self.child.write().expect("failed to lock child");
+        let old = current.replace(child);
+        if let Some(old) = old {
+            log::warn!("{} was already initialized, terminating old process", name);
+            old.kill();
+        }
+        drop(current);
+
+        match self.with_child(|child| child.handshake()) {
+            Ok(hello) => log::info!(
+                "Connected to the {} child {} ({}), protocol version {}",
+                name,
+                hello.version,
+                hello.arch,
+                hello.protocol_version
+            ),
+            Err(err) => {
+                log::error!(
+                    "The {} child is incompatible with this {}-pipe, make sure both come from the same build: {}",
+                    name,
+                    name,
+                    err
+                );
+                let mut current = self.child.write().expect("failed to lock child");
+                if let Some(child) = current.take() {
+                    child.kill();
+                }
+                return false;
+            }
+        }
+
+        let success = self.with_child(|child| child.init()).unwrap_or_else(|err| {
+            log::error!("Failed to initialize child {}: {:?}", name, err);
+            false
+        });
+
+        if success {
+            log::info!("{} initialized successfully", name);
+        }
+
+        success
+    }
+
+    /// Finalizes the library and stops the child.
+    pub fn fini(&self) {
+        let name = S::Api::NAME;
+        // Nothing runs after a failed init
+        if self.child.read().expect("failed to lock child").is_none() {
+            return;
+        }
+        if let Err(err) = self.call(S::Api::FINI) {
+            log::error!("Failed to finalize child {}: {:?}", name, err);
+        }
+
+        let mut current = self.child.write().expect("failed to lock child");
+        if let Some(child) = current.take() {
+            child.kill();
+            log::info!("{} finalized and child process terminated", name);
+        }
+    }
+
+    /// Serves a function called by the game, see [`Session::call`].
+    pub fn call(&self, call: CallOf<S>) -> Result<ReturnOf<S>, Error> {
+        self.with_child(|child| S::call(child, call))
+    }
+
+    /// Sends a request of the API to the child, for functions its table cannot forward.
+    pub fn request(&self, request: RequestOf<S>) -> Result<ResponseOf<S>, Error> {
+        self.with_child(|child| child.request(request))
+    }
+
+    /// Changes the most verbose level of the child's logs forwarded to the game's loggers, from
+    /// `0` to stop forwarding them up to `5` for trace. Returns `false` if the child cannot
+    /// forward logs.
+    pub fn set_child_log_level(&self, level: u8) -> bool {
+        let level = match level {
+            0 => None,
+            1 => Some(LogLevel::Error),
+            2 => Some(LogLevel::Warn),
+            3 => Some(LogLevel::Info),
+            4 => Some(LogLevel::Debug),
+            5 => Some(LogLevel::Trace),
+            _ => {
+                log::warn!("Invalid child log level {}", level);
+                return false;
+            }
+        };
+
+        let current = self.child.read().expect("failed to lock child");
+        let Some(child) = current.as_ref() else {
+            log::warn!(
+                "Cannot set the child log level before {} is initialized",
+                S::Api::NAME
+            );
+            return false;
+        };
+        child.set_log_level(level).unwrap_or_else(|err| {
+            log::warn!("Failed to set the child log level: {:?}", err);
+            false
+        })
+    }
+
+    /// Fails with [`Error::NotInitialized`] before `init`, after `fini` or after a failed `init`,
+    /// so that the exports return their default value instead of panicking.
+    fn with_child<T>(&self, func: impl FnOnce(&Child<S>) -> Result<T, Error>) -> Result<T, Error> {
+        let child = self.child.read().expect("failed to lock child");
+        let child = child.as_ref().ok_or(Error::NotInitialized)?;
+        // Calls come from the game's threads, where it is safe to log
+        child.drain_logs();
+        func(child)
+    }
+}
+
+impl<S: Session> Default for Host<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Generates the exports of an API's table calling the child of `$host`, a `Host<$session>`
+/// static: `$set_loggers` and the lifecycle functions, which set up and stop the child, and each
+/// input and output function, which is called on it.
+///
+/// Meant to be passed to the table, as in
+/// `sdvxio_api!(exports, HOST: SdvxioSession, sdvx_io_set_loggers)`.
+#[macro_export]
+macro_rules! exports {
+    (
+        $host:ident: $session:ty, $set_loggers:ident,
+        lifecycle {
+            $init_variant:ident = $init:ident as init() -> bool;
+            $fini_variant:ident = $fini:ident as fini() -> ();
+        }
+        $(
+            $kind:ident {
+                $($variant:ident = $export:ident as $method:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*
+            }
+        )*
+    ) => {
+        #[unsafe(no_mangle)]
+        pub extern "C" fn $set_loggers(
+            misc: $crate::glue::log_formatter_t,
+            info: $crate::glue::log_formatter_t,
+            warning: $crate::glue::log_formatter_t,
+            fatal: $crate::glue::log_formatter_t,
+        ) {
+            $host.set_loggers(misc, info, warning, fatal);
+        }
+
+        /// The game's thread functions are not used, as the library runs in the child.
+        #[unsafe(no_mangle)]
+        pub extern "C" fn $init(
+            _thread_create: $crate::glue::thread_create_t,
+            _thread_join: $crate::glue::thread_join_t,
+            _thread_destroy: $crate::glue::thread_destroy_t,
+        ) -> bool {
+            $crate::log::trace!(concat!(stringify!($init), " called"));
+            $host.init()
+        }
+
+        #[unsafe(no_mangle)]
+        pub extern "C" fn $fini() {
+            $crate::log::trace!(concat!(stringify!($fini), " called"));
+            $host.fini();
+        }
+
+        $($(
+            #[unsafe(no_mangle)]
+            pub extern "C" fn $export($($arg: $ty),*) -> $ret {
+                type Call = $crate::CallOf<$session>;
+                type Return = $crate::ReturnOf<$session>;
+
+                $crate::log::trace!(concat!(stringify!($export), " called"));
+
+                $host
+                    .call(Call::$variant { $($arg),* })
+                    .and_then(|value| match value {
+                        Return::$variant(value) => Ok(value),
+                        _ => Err($crate::Error::WrongResponseType),
+                    })
+                    .unwrap_or_else(|err| {
+                        $crate::log::error!(
+                            concat!("Failed to call ", stringify!($export), " on the child: {:?}"),
+                            err
+                        );
+                        Default::default()
+                    })
+            }
+        )*)*
+    };
+}
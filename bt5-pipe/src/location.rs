@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+
+/// Directory containing the proxy library, which the game loaded in place of the wrapped one.
+pub fn module_dir() -> std::io::Result<PathBuf> {
+    let path = sys::module_path()?;
+    path.parent()
+        .map(PathBuf::from)
+        .ok_or_else(|| std::io::Error::other(format!("{} has no parent", path.display())))
+}
+
+/// An address inside this library, to look it up by.
+fn anchor() -> *const std::ffi::c_void {
+    module_dir as *const std::ffi::c_void
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::{OsString, c_void};
+    use std::os::windows::ffi::OsStringExt;
+    use std::path::PathBuf;
+
+    const GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT: u32 = 0x2;
+    const GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS: u32 = 0x4;
+
+    unsafe extern "system" {
+        fn GetModuleHandleExW(
+            flags: u32,
+            module_name: *const c_void,
+            module: *mut *mut c_void,
+        ) -> i32;
+        fn GetModuleFileNameW(module: *mut c_void, filename: *mut u16, size: u32) -> u32;
+    }
+
+    pub fn module_path() -> std::io::Result<PathBuf> {
+        let mut module = std::ptr::null_mut();
+        let flags =
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT;
+        if unsafe { GetModuleHandleExW(flags, super::anchor(), &mut module) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // The name is truncated to the buffer, so grow it until the whole path fits
+        let mut buffer = vec![0u16; 260];
+        loop {
+            let length =
+                unsafe { GetModuleFileNameW(module, buffer.as_mut_ptr(), buffer.len() as u32) };
+            if length == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            if (length as usize) < buffer.len() {
+                return Ok(PathBuf::from(OsString::from_wide(
+                    &buffer[..length as usize],
+                )));
+            }
+            buffer.resize(buffer.len() * 2, 0);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{CStr, OsStr, c_char, c_int, c_void};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    #[repr(C)]
+    struct DlInfo {
+        fname: *const c_char,
+        fbase: *mut c_void,
+        sname: *const c_char,
+        saddr: *mut c_void,
+    }
+
+    #[cfg_attr(target_os = "linux", link(name = "dl"))]
+    unsafe extern "C" {
+        fn dladdr(address: *const c_void, info: *mut DlInfo) -> c_int;
+    }
+
+    pub fn module_path() -> std::io::Result<PathBuf> {
+        let mut info = DlInfo {
+            fname: std::ptr::null(),
+            fbase: std::ptr::null_mut(),
+            sname: std::ptr::null(),
+            saddr: std::ptr::null_mut(),
+        };
+        if unsafe { dladdr(super::anchor(), &mut info) } == 0 || info.fname.is_null() {
+            return Err(std::io::Error::other("cannot find the loaded library"));
+        }
+        let path = unsafe { CStr::from_ptr(info.fname) };
+        let path = PathBuf::from(OsStr::from_bytes(path.to_bytes()));
+        // The name is the one the library was opened with, which may be relative
+        Ok(std::env::current_dir()?.join(path))
+    }
+}
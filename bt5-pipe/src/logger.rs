@@ -0,0 +1,64 @@
+use crate::glue::log_formatter_t;
+use log::{Level, Log, Metadata, Record, SetLoggerError};
+use std::ffi::CString;
+use std::sync::OnceLock;
+
+static LOGGER: OnceLock<BT5Logger> = OnceLock::new();
+
+#[derive(Debug)]
+pub struct BT5Logger {
+    /// Module name of records logged by the proxy itself, such as `sdvxio-pipe`.
+    pub(crate) module: String,
+    pub(crate) misc: log_formatter_t,
+    pub(crate) info: log_formatter_t,
+    pub(crate) warning: log_formatter_t,
+    pub(crate) fatal: log_formatter_t,
+}
+
+impl BT5Logger {
+    /// Installs the logger, keeping it reachable for records forwarded by the child.
+    pub fn install(self) -> Result<(), SetLoggerError> {
+        log::set_logger(LOGGER.get_or_init(|| self))
+    }
+
+    fn write(&self, level: Level, module: &str, message: &str) {
+        let logger = match level {
+            Level::Error => self.fatal,
+            Level::Warn => self.warning,
+            Level::Info => self.info,
+            Level::Debug | Level::Trace => self.misc,
+        };
+        let module = c_string(module);
+        let message = c_string(message);
+        unsafe {
+            if let Some(logger_fn) = logger {
+                logger_fn(module.as_ptr(), message.as_ptr());
+            }
+        }
+    }
+}
+
+impl Log for BT5Logger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.write(record.level(), &self.module, &format!("{}", record.args()));
+    }
+
+    fn flush(&self) {
+        // No-op
+    }
+}
+
+/// Emits a record forwarded by the child, under the module name it was logged with.
+pub fn forward(level: Level, target: &str, message: &str) {
+    if let Some(logger) = LOGGER.get() {
+        logger.write(level, target, message);
+    }
+}
+
+fn c_string(value: &str) -> CString {
+    CString::new(value.replace('\0', " ")).unwrap()
+}
@@ -0,0 +1,285 @@
+use crate::error::Error;
+use sdvxio_pipe_proto::{Api, ChildToParent, Message, ParentToChild, encode_frame};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// Matches responses to the requests waiting for them by message id, so that requests from
+/// several game threads can be in flight at once.
+///
+/// Requests are written by a thread of their own, so that a child that stops reading cannot
+/// block the game's threads past the deadline of their requests.
+pub struct Multiplexer<A: Api> {
+    /// Frames queued for the writer thread, in the order they are sent. `None` once
+    /// disconnected, which ends the writer thread once it is done writing.
+    frames: Mutex<Option<mpsc::Sender<Vec<u8>>>>,
+    pending: Mutex<Pending<A>>,
+}
+
+struct Pending<A: Api> {
+    waiting: HashMap<u32, mpsc::Sender<Delivery<A>>>,
+    /// Why the connection closed, failing every later request.
+    closed: Option<(std::io::ErrorKind, String)>,
+}
+
+/// What the reader thread hands to a waiting request.
+enum Delivery<A: Api> {
+    Response(ChildToParent<A>),
+    /// A frame was lost, which may have been the response.
+    Corrupt(usize),
+    Closed(std::io::Error),
+}
+
+/// A request sent to the child, waiting for its response.
+pub struct Ticket<'a, A: Api> {
+    mux: &'a Multiplexer<A>,
+    id: u32,
+    deliveries: mpsc::Receiver<Delivery<A>>,
+}
+
+impl<A: Api> Multiplexer<A> {
+    /// Starts the writer thread, which must not log as it is not created through the game's
+    /// thread API.
+    pub fn new(mut writer: Box<dyn Write + Send>) -> std::io::Result<Arc<Self>> {
+        let (frames, queued) = mpsc::channel::<Vec<u8>>();
+        let mux = Arc::new(Self {
+            frames: Mutex::new(Some(frames)),
+            pending: Mutex::new(Pending {
+                waiting: HashMap::new(),
+                closed: None,
+            }),
+        });
+
+        let weak: Weak<Self> = Arc::downgrade(&mux);
+        std::thread::Builder::new()
+            .name(format!("{}-pipe-writer", A::NAME))
+            .spawn(move || {
+                for frame in queued {
+                    if let Err(err) = writer.write_all(&frame).and_then(|()| writer.flush()) {
+                        if let Some(mux) = weak.upgrade() {
+                            mux.close(&err);
+                        }
+                        break;
+                    }
+                }
+            })?;
+        Ok(mux)
+    }
+
+    /// Queues a request for writing, registering it first so that its response cannot be
+    /// missed. Never blocks on the connection: a request that cannot be written times out.
+    pub fn send(&self, msg: ParentToChild<A>) -> Result<Ticket<'_, A>, Error> {
+        let message = Message::new(msg);
+        let frame = encode_frame(&message)?;
+        let (deliver, deliveries) = mpsc::channel();
+        {
+            let mut pending = self.pending();
+            if let Some((kind, reason)) = &pending.closed {
+                return Err(std::io::Error::new(*kind, reason.clone()).into());
+            }
+            pending.waiting.insert(message.id, deliver);
+        }
+
+        // Dropping the ticket unregisters the request if sending fails
+        let ticket = Ticket {
+            mux: self,
+            id: message.id,
+            deliveries,
+        };
+        let frames = self.frames.lock().expect("failed to lock child sender");
+        match frames.as_ref().map(|frames| frames.send(frame)) {
+            Some(Ok(())) => Ok(ticket),
+            // The writer thread stopped after failing to write
+            Some(Err(_)) | None => Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe).into()),
+        }
+    }
+
+    /// Hands a response to the request waiting for it. Late answers to requests that gave up
+    /// waiting are dropped.
+    pub fn dispatch(&self, response: Message<ChildToParent<A>>) {
+        if let Some(waiting) = self.pending().waiting.remove(&response.id) {
+            let _ = waiting.send(Delivery::Response(response.payload));
+        }
+    }
+
+    /// Tells every waiting request that a frame was lost, as it may have been its response.
+    pub fn corrupt(&self, discarded: usize) {
+        for waiting in self.pending().waiting.values() {
+            let _ = waiting.send(Delivery::Corrupt(discarded));
+        }
+    }
+
+    /// Fails every waiting and later request with `err`.
+    pub fn close(&self, err: &std::io::Error) {
+        let mut pending = self.pending();
+        pending.closed = Some((err.kind(), err.to_string()));
+        for (_, waiting) in pending.waiting.drain() {
+            let _ = waiting.send(Delivery::Closed(std::io::Error::new(
+                err.kind(),
+                err.to_string(),
+            )));
+        }
+    }
+
+    /// Closes the sending side, which some transports need to release their resources. The
+    /// writer is dropped once the frame being written, if any, is done.
+    pub fn disconnect(&self) {
+        self.frames
+            .lock()
+            .expect("failed to lock child sender")
+            .take();
+        self.close(&std::io::ErrorKind::BrokenPipe.into());
+    }
+
+    fn pending(&self) -> std::sync::MutexGuard<'_, Pending<A>> {
+        self.pending
+            .lock()
+            .expect("failed to lock pending requests")
+    }
+}
+
+impl<A: Api> Ticket<'_, A> {
+    /// Waits for the response, giving up after `deadline` if given.
+    pub fn wait(self, deadline: Option<Duration>) -> Result<ChildToParent<A>, Error> {
+        let started = Instant::now();
+        let mut discarded = 0;
+        loop {
+            let delivery = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_sub(started.elapsed());
+                    match self.deliveries.recv_timeout(remaining) {
+                        Ok(delivery) => delivery,
+                        Err(RecvTimeoutError::Timeout) if discarded > 0 => {
+                            return Err(Error::CorruptFrame { discarded });
+                        }
+                        Err(RecvTimeoutError::Timeout) => {
+                            return Err(Error::Timeout { after: deadline });
+                        }
+                        Err(RecvTimeoutError::Disconnected) => {
+                            return Err(
+                                std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()
+                            );
+                        }
+                    }
+                }
+                None => self
+                    .deliveries
+                    .recv()
+                    .unwrap_or_else(|_| Delivery::Closed(std::io::ErrorKind::UnexpectedEof.into())),
+            };
+
+            match delivery {
+                Delivery::Response(ChildToParent::Error { kind, message, .. }) => {
+                    return Err(Error::from_child(kind, message));
+                }
+                Delivery::Response(response) => return Ok(response),
+                // The corrupted frame may not have been our response, so keep waiting for it,
+                // until the deadline if there is one
+                Delivery::Corrupt(frame) => discarded += frame,
+                Delivery::Closed(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+impl<A: Api> Drop for Ticket<'_, A> {
+    fn drop(&mut self) {
+        self.mux.pending().waiting.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdvxio_pipe_proto::sdvxio::{Call, Return, Sdvxio};
+    use sdvxio_pipe_proto::{Receiver, Sender};
+
+    fn spinner_pos(spinner_no: u8) -> ParentToChild<Sdvxio> {
+        ParentToChild::Call(Call::GetSpinnerPos { spinner_no })
+    }
+
+    fn returned(response: ChildToParent<Sdvxio>) -> Return {
+        match response {
+            ChildToParent::Return(ret) => ret,
+            response => panic!("unexpected response {:?}", response),
+        }
+    }
+
+    #[test]
+    fn waits_past_a_corrupt_frame_without_a_deadline() {
+        let mux = Multiplexer::<Sdvxio>::new(Box::new(std::io::sink())).unwrap();
+        let first = mux.send(spinner_pos(0)).unwrap();
+        let second = mux.send(spinner_pos(1)).unwrap();
+        let (first_id, second_id) = (first.id, second.id);
+
+        std::thread::scope(|scope| {
+            let first = scope.spawn(|| first.wait(None));
+            mux.corrupt(12);
+            mux.dispatch(Message::with_id(
+                second_id,
+                ChildToParent::Return(Return::GetSpinnerPos(2)),
+            ));
+            assert_eq!(
+                returned(second.wait(None).unwrap()),
+                Return::GetSpinnerPos(2)
+            );
+
+            // The corrupt frame was not the response of the first request either
+            std::thread::sleep(Duration::from_millis(20));
+            assert!(!first.is_finished());
+            mux.dispatch(Message::with_id(
+                first_id,
+                ChildToParent::Return(Return::GetSpinnerPos(1)),
+            ));
+            assert_eq!(
+                returned(first.join().unwrap().unwrap()),
+                Return::GetSpinnerPos(1)
+            );
+        });
+    }
+
+    #[test]
+    fn hands_responses_answered_out_of_order_to_their_requests() {
+        let (requests, requests_tx) = std::io::pipe().unwrap();
+        let (responses, responses_tx) = std::io::pipe().unwrap();
+        let mux = Multiplexer::<Sdvxio>::new(Box::new(requests_tx)).unwrap();
+
+        // A child answering the two requests in reverse order
+        std::thread::spawn(move || {
+            let mut rx = Receiver::<_, Message<ParentToChild<Sdvxio>>>::new(requests);
+            let first = rx.recv().unwrap();
+            let second = rx.recv().unwrap();
+            let mut tx = Sender::new(responses_tx);
+            for request in [second, first] {
+                let ParentToChild::Call(Call::GetSpinnerPos { spinner_no }) = request.payload
+                else {
+                    panic!("unexpected request");
+                };
+                let pos = Return::GetSpinnerPos(u16::from(spinner_no) * 10);
+                tx.send(&request.reply(ChildToParent::<Sdvxio>::Return(pos)))
+                    .unwrap();
+            }
+        });
+        let reader_mux = mux.clone();
+        std::thread::spawn(move || {
+            let mut rx = Receiver::new(responses);
+            while let Ok(response) = rx.recv() {
+                reader_mux.dispatch(response);
+            }
+        });
+
+        let first = mux.send(spinner_pos(1)).unwrap();
+        let second = mux.send(spinner_pos(2)).unwrap();
+        let deadline = Some(Duration::from_secs(5));
+        assert_eq!(
+            returned(first.wait(deadline).unwrap()),
+            Return::GetSpinnerPos(10)
+        );
+        assert_eq!(
+            returned(second.wait(deadline).unwrap()),
+            Return::GetSpinnerPos(20)
+        );
+    }
+}
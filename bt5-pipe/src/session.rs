@@ -0,0 +1,58 @@
+use crate::child::{Child, Process};
+use crate::config::TimeoutConfig;
+use crate::error::Error;
+use sdvxio_pipe_proto::Api;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+pub type CallOf<S> = <<S as Session>::Api as Api>::Call;
+pub type ReturnOf<S> = <<S as Session>::Api as Api>::Return;
+pub type RequestOf<S> = <<S as Session>::Api as Api>::Request;
+pub type ResponseOf<S> = <<S as Session>::Api as Api>::Response;
+
+/// What the proxy of an API adds to its [`Child`], such as serving functions from state kept in
+/// the parent. Kept along with the rest of the child's state, see [`Child::session`].
+pub trait Session: Default + Send + 'static {
+    type Api: Api;
+    /// Deadlines of the API's own functions and requests, read from the `[timeouts.<api>]` table
+    /// of the configuration, such as `[timeouts.sdvxio]`. [`NoTimeouts`] if it has none.
+    type Timeouts: DeserializeOwned + Send + Sync + 'static;
+
+    /// Serves a function called by the game, forwarding it to the child by default.
+    fn call(child: &Child<Self>, call: CallOf<Self>) -> Result<ReturnOf<Self>, Error> {
+        child.forward(call)
+    }
+
+    /// Keeps track of a function forwarded to the child, to restore its effect after a respawn.
+    fn record(&mut self, _call: &CallOf<Self>) {}
+
+    /// Called once the library initialized on a new child, before other requests reach it.
+    fn started(_child: &Child<Self>, _process: &Process<Self::Api>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Restores what the game set on a respawned child, after [`Session::started`].
+    fn restore(_child: &Child<Self>, _process: &Process<Self::Api>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Deadline of a function in milliseconds, `0` waiting forever.
+    fn timeout(timeouts: &TimeoutConfig, _api: &Self::Timeouts, call: &CallOf<Self>) -> u64 {
+        timeouts.call::<Self::Api>(call)
+    }
+
+    /// Deadline of a request in milliseconds, `0` waiting forever.
+    fn request_timeout(
+        timeouts: &TimeoutConfig,
+        _api: &Self::Timeouts,
+        _request: &RequestOf<Self>,
+    ) -> u64 {
+        timeouts.request_ms
+    }
+}
+
+/// The [`Session::Timeouts`] of an API whose functions and requests all take the generic
+/// deadlines.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NoTimeouts {}
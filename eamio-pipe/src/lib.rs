@@ -0,0 +1,85 @@
+use crate::session::EamioSession;
+use bt5_pipe::{Host, exports};
+use sdvxio_pipe_proto::eamio::{CARD_ID_SIZE, Request, Response};
+use sdvxio_pipe_proto::eamio_api;
+use std::ffi::c_void;
+
+mod session;
+
+static HOST: Host<EamioSession> = Host::new();
+
+eamio_api!(exports, HOST: EamioSession, eam_io_set_loggers);
+
+/// Reads the card in the slot of `unit_no` into `card_id`, writing at most `nbytes` bytes of
+/// its ID, and returns its type.
+///
+/// # Safety
+///
+/// `card_id` must be null or valid for writing `nbytes` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn eam_io_read_card(unit_no: u8, card_id: *mut u8, nbytes: u8) -> u8 {
+    log::trace!("eam_io_read_card called");
+
+    match HOST.request(Request::ReadCard { unit_no }) {
+        Ok(Response::ReadCard {
+            card_type,
+            card_id: id,
+        }) => {
+            unsafe { copy_card_id(&id, card_id, nbytes) };
+            card_type
+        }
+        Err(err) => {
+            log::error!("Failed to call eam_io_read_card on the child: {:?}", err);
+            0
+        }
+    }
+}
+
+/// Copies at most `nbytes` bytes of `id` to `card_id`, unless it is null.
+///
+/// # Safety
+///
+/// `card_id` must be null or valid for writing `nbytes` bytes.
+unsafe fn copy_card_id(id: &[u8; CARD_ID_SIZE], card_id: *mut u8, nbytes: u8) {
+    let len = (nbytes as usize).min(CARD_ID_SIZE);
+    if !card_id.is_null() {
+        unsafe { std::ptr::copy_nonoverlapping(id.as_ptr(), card_id, len) };
+    }
+}
+
+/// The configuration API of the wrapped library cannot be called across processes, so there is
+/// none.
+#[unsafe(no_mangle)]
+pub extern "C" fn eam_io_get_config_api() -> *const c_void {
+    std::ptr::null()
+}
+
+/// Changes the most verbose level of the child's logs forwarded to the game's loggers, from `0`
+/// to stop forwarding them up to `5` for trace. Returns `false` if the child cannot forward logs.
+#[unsafe(no_mangle)]
+pub extern "C" fn eamio_pipe_set_child_log_level(level: u8) -> bool {
+    HOST.set_child_log_level(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: [u8; CARD_ID_SIZE] = [0xe0, 0x04, 1, 2, 3, 4, 5, 6];
+
+    #[test]
+    fn copies_at_most_nbytes_of_the_card_id() {
+        let mut buffer = [0xff; 2 * CARD_ID_SIZE];
+        unsafe { copy_card_id(&ID, buffer.as_mut_ptr(), 3) };
+        assert_eq!(buffer[..3], ID[..3]);
+        assert!(buffer[3..].iter().all(|&byte| byte == 0xff));
+
+        // Never more than the ID, whatever the game asks for
+        let mut buffer = [0xff; 2 * CARD_ID_SIZE];
+        unsafe { copy_card_id(&ID, buffer.as_mut_ptr(), u8::MAX) };
+        assert_eq!(buffer[..CARD_ID_SIZE], ID);
+        assert!(buffer[CARD_ID_SIZE..].iter().all(|&byte| byte == 0xff));
+
+        unsafe { copy_card_id(&ID, std::ptr::null_mut(), 8) };
+    }
+}
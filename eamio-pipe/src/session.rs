@@ -0,0 +1,11 @@
+use bt5_pipe::{NoTimeouts, Session};
+use sdvxio_pipe_proto::eamio::Eamio;
+
+/// Functions are forwarded as they are, card readers keep no state worth restoring.
+#[derive(Default)]
+pub struct EamioSession;
+
+impl Session for EamioSession {
+    type Api = Eamio;
+    type Timeouts = NoTimeouts;
+}
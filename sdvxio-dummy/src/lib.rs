@@ -0,0 +1,136 @@
+//! A `sdvxio` library without hardware, to run the parent and the program end to end.
+//!
+//! Each `sdvx_io_read_input` advances a simulated frame: the knobs turn in opposite directions
+//! and the start button is held every other second at 60 reads per second. Lights and amp volume
+//! are logged when written, and initializing prints to stdout as some libraries do.
+
+use sdvxio_pipe_proto::sdvxio::{AmpVolume, GameButtons, GpioLights, SpinnerPos};
+use std::ffi::{CString, c_char, c_int, c_void};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+type LogFormatter = Option<unsafe extern "C" fn(module: *const c_char, fmt: *const c_char, ...)>;
+type ThreadCreate = Option<
+    unsafe extern "C" fn(
+        proc: Option<unsafe extern "C" fn(*mut c_void) -> c_int>,
+        ctx: *mut c_void,
+        stack_sz: u32,
+        priority: u32,
+    ) -> c_int,
+>;
+type ThreadJoin = Option<unsafe extern "C" fn(thread_id: c_int, result: *mut c_int)>;
+type ThreadDestroy = Option<unsafe extern "C" fn(thread_id: c_int)>;
+
+const READS_PER_SECOND: u32 = 60;
+
+static INFO: Mutex<LogFormatter> = Mutex::new(None);
+static FRAME: AtomicU32 = AtomicU32::new(0);
+static GPIO_LIGHTS: AtomicU32 = AtomicU32::new(0);
+
+fn info(message: String) {
+    let Some(info) = *INFO.lock().unwrap() else {
+        return;
+    };
+    let message = CString::new(message).unwrap_or_default();
+    unsafe { info(c"sdvxio-dummy".as_ptr(), c"%s".as_ptr(), message.as_ptr()) };
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sdvx_io_set_loggers(
+    _misc: LogFormatter,
+    info: LogFormatter,
+    _warning: LogFormatter,
+    _fatal: LogFormatter,
+) {
+    *INFO.lock().unwrap() = info;
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sdvx_io_init(
+    _thread_create: ThreadCreate,
+    _thread_join: ThreadJoin,
+    _thread_destroy: ThreadDestroy,
+) -> bool {
+    info("Initialized".to_owned());
+    println!("sdvxio-dummy printed to stdout");
+    true
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sdvx_io_fini() {
+    info("Finalized".to_owned());
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sdvx_io_set_gpio_lights(gpio_lights: u32) {
+    if GPIO_LIGHTS.swap(gpio_lights, Ordering::Relaxed) != gpio_lights {
+        info(format!(
+            "GPIO lights {:?}",
+            GpioLights::from_bits_retain(gpio_lights)
+        ));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sdvx_io_set_pwm_light(light_no: u8, intensity: u8) {
+    info(format!("PWM light {} set to {}", light_no, intensity));
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sdvx_io_write_output() -> bool {
+    true
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sdvx_io_read_input() -> bool {
+    FRAME.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sdvx_io_get_input_gpio_sys() -> u8 {
+    0
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sdvx_io_get_input_gpio(gpio_bank: u8) -> u16 {
+    let frame = FRAME.load(Ordering::Relaxed);
+    let buttons = if (frame / READS_PER_SECOND) % 2 == 1 {
+        GameButtons::START
+    } else {
+        GameButtons::empty()
+    };
+    buttons
+        .to_gpio()
+        .get(gpio_bank as usize)
+        .copied()
+        .unwrap_or(0)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sdvx_io_get_spinner_pos(spinner_no: u8) -> u16 {
+    let frame = FRAME.load(Ordering::Relaxed);
+    let turned = (frame % SpinnerPos::RANGE as u32) as i16;
+    let position = match spinner_no {
+        0 => SpinnerPos::default().wrapping_add(turned),
+        _ => SpinnerPos::default().wrapping_add(-turned),
+    };
+    position.get()
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sdvx_io_set_amp_volume(primary: u8, headphone: u8, subwoofer: u8) -> bool {
+    let volumes = [primary, headphone, subwoofer].map(AmpVolume::new);
+    if volumes.contains(&None) {
+        info(format!(
+            "Rejected amp volume {} primary, {} headphone, {} subwoofer",
+            primary, headphone, subwoofer
+        ));
+        return false;
+    }
+    info(format!(
+        "Amp volume set to {} primary, {} headphone, {} subwoofer",
+        primary, headphone, subwoofer
+    ));
+    true
+}
@@ -0,0 +1,108 @@
+use sdvxio_pipe_proto::sdvxio::Sdvxio;
+use sdvxio_pipe_proto::{Api, Endpoint, PreSharedKey};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// Command line arguments, passed by the parent or given when running on another machine.
+#[derive(Debug, Default)]
+pub struct Args {
+    /// API served to parents, `sdvxio` by default.
+    pub api: Option<String>,
+    /// Wrapped library, instead of the one named after the API found next to the program, such as
+    /// `sdvxio.dll` or `libsdvxio.so`.
+    pub library: Option<PathBuf>,
+    /// Inspects the library without loading it, prints a report and exits.
+    pub check: bool,
+    /// Shared memory file created by the parent, used instead of stdin and stdout.
+    pub shm: Option<PathBuf>,
+    /// Connects to a parent listening on this endpoint.
+    pub connect: Option<Endpoint>,
+    /// Serves parents connecting to this endpoint, one at a time.
+    pub listen: Option<Endpoint>,
+    /// Exits after the first parent disconnects instead of waiting for another one.
+    pub once: bool,
+    /// File holding the pre-shared key, instead of `<API>_PIPE_PSK`, as in `SDVXIO_PIPE_PSK`.
+    pub psk_file: Option<PathBuf>,
+    /// Calls the library from a single thread, for libraries that cannot have input and output
+    /// driven concurrently.
+    pub serial: bool,
+    /// Arguments this program does not know, ignored once the logger is set up.
+    pub unknown: Vec<OsString>,
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut args = Args::default();
+        let mut iter = std::env::args_os().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.to_str() {
+                Some("--api") => {
+                    let api = iter.next().ok_or("--api requires a name")?;
+                    let api = api
+                        .into_string()
+                        .map_err(|api| format!("invalid API {:?}", api))?;
+                    args.api = Some(api);
+                }
+                Some("--library") => {
+                    let path = iter.next().ok_or("--library requires a path")?;
+                    args.library = Some(PathBuf::from(path));
+                }
+                Some("--shm") => {
+                    let path = iter.next().ok_or("--shm requires a path")?;
+                    args.shm = Some(PathBuf::from(path));
+                }
+                Some("--connect") => args.connect = Some(endpoint("--connect", iter.next())?),
+                Some("--listen") => args.listen = Some(endpoint("--listen", iter.next())?),
+                Some("--check") => args.check = true,
+                Some("--once") => args.once = true,
+                Some("--serial") => args.serial = true,
+                Some("--psk-file") => {
+                    let path = iter.next().ok_or("--psk-file requires a path")?;
+                    args.psk_file = Some(PathBuf::from(path));
+                }
+                _ => args.unknown.push(arg),
+            }
+        }
+
+        let transports = [
+            args.shm.is_some(),
+            args.connect.is_some(),
+            args.listen.is_some(),
+        ];
+        if transports.into_iter().filter(|&set| set).count() > 1 {
+            return Err("--shm, --connect and --listen are mutually exclusive".to_owned());
+        }
+        Ok(args)
+    }
+
+    pub fn api(&self) -> &str {
+        self.api.as_deref().unwrap_or(Sdvxio::NAME)
+    }
+
+    /// Key that socket connections to the parent of `api` must prove, if any.
+    pub fn psk(&self, api: &str) -> Result<Option<PreSharedKey>, String> {
+        let secret = match &self.psk_file {
+            Some(path) => std::fs::read_to_string(path)
+                .map(|secret| secret.trim_end_matches(['\r', '\n']).to_owned())
+                .map_err(|err| format!("cannot read {}: {}", path.display(), err))?,
+            None => {
+                let name = format!("{}_PIPE_PSK", api.to_ascii_uppercase());
+                match std::env::var(&name) {
+                    Ok(secret) => secret,
+                    Err(std::env::VarError::NotPresent) => return Ok(None),
+                    Err(err) => return Err(format!("invalid {}: {}", name, err)),
+                }
+            }
+        };
+        Ok(Some(PreSharedKey::new(secret)))
+    }
+}
+
+fn endpoint(name: &str, value: Option<OsString>) -> Result<Endpoint, String> {
+    let value = value.ok_or_else(|| format!("{} requires an endpoint", name))?;
+    value
+        .to_str()
+        .ok_or_else(|| format!("invalid endpoint {:?}", value))?
+        .parse()
+        .map_err(|err| format!("{}", err))
+}
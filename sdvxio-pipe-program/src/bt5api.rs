@@ -3,20 +3,152 @@
 #![allow(non_snake_case)]
 #![allow(unused)]
 
-#[link(name = "sdvxio", kind = "dylib")]
-
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
-use slotmap::{new_key_type, KeyData, SlotMap};
+use sdvxio_pipe_proto::Api;
+use slotmap::{KeyData, SlotMap, new_key_type};
 use std::ffi::CStr;
 use std::os::raw::{c_int, c_uint, c_void};
+use std::path::{Path, PathBuf};
 use std::{
-    os::windows::io::AsRawHandle,
-    sync::{LazyLock, Mutex},
+    sync::{LazyLock, Mutex, MutexGuard, PoisonError},
     thread::JoinHandle,
 };
 use thread_priority::*;
 
+/// Declares the functions resolved from the wrapped library of an API, along with wrappers
+/// calling them, [`load`] resolving them and the list of optional ones.
+///
+/// Required functions must all be exported for the library to load. Optional ones may be missing,
+/// in which case their wrapper returns `None` and their capability is not advertised.
+///
+/// The headers give no calling convention, so the functions are `cdecl` on 32-bit Windows too.
+macro_rules! library {
+    (
+        required {
+            $(fn $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;)*
+        }
+        optional {
+            $(fn $opt_name:ident($($opt_arg:ident: $opt_ty:ty),*) -> $opt_ret:ty
+                => $capability:expr;)*
+        }
+    ) => {
+        struct Library {
+            _library: libloading::Library,
+            $($name: unsafe extern "C" fn($($ty),*) $(-> $ret)?,)*
+            $($opt_name: Option<unsafe extern "C" fn($($opt_ty),*) -> $opt_ret>,)*
+        }
+
+        impl Library {
+            fn resolve(library: libloading::Library) -> Result<Self, String> {
+                let mut missing = Vec::new();
+                $(let $name = unsafe {
+                    library.get::<unsafe extern "C" fn($($ty),*) $(-> $ret)?>(
+                        concat!(stringify!($name), "\0").as_bytes(),
+                    )
+                };
+                let $name = match $name {
+                    Ok(symbol) => Some(*symbol),
+                    Err(_) => {
+                        missing.push(stringify!($name));
+                        None
+                    }
+                };)*
+                if !missing.is_empty() {
+                    return Err(format!("missing required functions {}", missing.join(", ")));
+                }
+
+                $(let $opt_name = unsafe {
+                    library.get::<unsafe extern "C" fn($($opt_ty),*) -> $opt_ret>(
+                        concat!(stringify!($opt_name), "\0").as_bytes(),
+                    )
+                }
+                .map(|symbol| *symbol)
+                .ok();
+                if $opt_name.is_none() {
+                    log::warn!("Optional function {} is not exported", stringify!($opt_name));
+                })*
+
+                Ok(Self {
+                    _library: library,
+                    $($name: $name.unwrap(),)*
+                    $($opt_name,)*
+                })
+            }
+        }
+
+        static LIBRARY: std::sync::OnceLock<Library> = std::sync::OnceLock::new();
+
+        /// Functions the library may not export.
+        pub const OPTIONAL: &[&str] = &[$(stringify!($opt_name)),*];
+
+        /// Loads the wrapped library and resolves its functions, which must be done before calling
+        /// any.
+        pub fn load(path: &std::path::Path) -> Result<(), String> {
+            let library = $crate::bt5api::open(path)
+                .map_err(|err| format!("cannot load {}: {}", path.display(), err))?;
+            let library = Library::resolve(library)
+                .map_err(|err| format!("{} is unusable: {}", path.display(), err))?;
+            LIBRARY
+                .set(library)
+                .map_err(|_| "the library is already loaded".to_owned())
+        }
+
+        fn library() -> &'static Library {
+            LIBRARY.get().expect("the wrapped library is not loaded")
+        }
+
+        /// Capabilities the loaded library lacks, as it does not export their optional functions.
+        pub fn missing_capabilities() -> sdvxio_pipe_proto::Capabilities {
+            // Never reassigned when every function is required
+            #[allow(unused_mut)]
+            let mut missing = sdvxio_pipe_proto::Capabilities::empty();
+            $(if library().$opt_name.is_none() {
+                missing = missing | $capability;
+            })*
+            missing
+        }
+
+        $(pub unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+            unsafe { (library().$name)($($arg),*) }
+        })*
+
+        $(pub unsafe fn $opt_name($($opt_arg: $opt_ty),*) -> Option<$opt_ret> {
+            library().$opt_name.map(|function| unsafe { function($($opt_arg),*) })
+        })*
+    };
+}
+
+pub(crate) use library;
+
+/// The library of `api` when none is given, next to the program. Not every platform searches
+/// the program's directory for a bare file name.
+pub fn default_library(api: &str) -> PathBuf {
+    let name = libloading::library_filename(api);
+    match std::env::current_exe() {
+        Ok(program) => program.with_file_name(name),
+        Err(_) => PathBuf::from(name),
+    }
+}
+
+#[cfg(windows)]
+pub fn open(path: &Path) -> Result<libloading::Library, libloading::Error> {
+    use libloading::os::windows::{LOAD_WITH_ALTERED_SEARCH_PATH, Library};
+
+    // Lets the libraries it depends on be found next to it rather than next to the program
+    let flags = if path.is_absolute() {
+        LOAD_WITH_ALTERED_SEARCH_PATH
+    } else {
+        0
+    };
+    unsafe { Library::load_with_flags(path, flags) }.map(Into::into)
+}
+
+#[cfg(not(windows))]
+pub fn open(path: &Path) -> Result<libloading::Library, libloading::Error> {
+    unsafe { libloading::Library::new(path) }
+}
+
 new_key_type! {
     struct ThreadKey;
 }
@@ -36,10 +168,19 @@ impl ThreadKey {
     }
 }
 
+/// Serializes input calls into the wrapped library between the input lane, the input stream and
+/// requests that must run alone.
+pub static INPUT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Locks [`INPUT_LOCK`], which a panic caught while holding it leaves poisoned.
+pub fn lock_input() -> MutexGuard<'static, ()> {
+    INPUT_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 static THREADS: LazyLock<Mutex<SlotMap<ThreadKey, JoinHandle<c_int>>>> =
     LazyLock::new(|| Mutex::new(SlotMap::with_key()));
 
-pub unsafe extern "C" fn create_thread(
+pub unsafe extern "C" fn create_thread<A: Api>(
     proc: Option<unsafe extern "C" fn(arg1: *mut c_void) -> c_int>,
     ctx: *mut c_void,
     stack_sz: u32,
@@ -57,19 +198,20 @@ pub unsafe extern "C" fn create_thread(
     unsafe impl Send for PointerWrapper {}
     let ctx = PointerWrapper(ctx);
 
-    let Ok(handle) = ThreadBuilder::default()
-        .name(format!("sdvxio-pipe-{}", guard.len()))
-        .stack_size(stack_sz as usize * 1024) // TODO: ???
-        .priority(ThreadPriority::Os(
-            priority
-                .try_into()
-                .unwrap_or(ThreadPriorityOsValue::default()),
-        ))
-        .spawn_careless(move || {
-            let _ = &ctx;
-            unsafe { proc(ctx.0) }
-        })
-    else {
+    let builder = ThreadBuilder::default()
+        .name(format!("{}-pipe-{}", A::NAME, guard.len()))
+        .stack_size(stack_sz as usize * 1024); // TODO: ???
+    // The priority is a Windows thread priority, other platforms keep the default one
+    #[cfg(windows)]
+    let builder = builder.priority(ThreadPriority::Os(
+        priority
+            .try_into()
+            .unwrap_or(ThreadPriorityOsValue::default()),
+    ));
+    let Ok(handle) = builder.spawn_careless(move || {
+        let _ = &ctx;
+        unsafe { proc(ctx.0) }
+    }) else {
         log::error!("Failed to create thread");
         return -1;
     };
@@ -124,6 +266,11 @@ macro_rules! decl_level {
 
 decl_level!({ MISC => Debug }, { INFO => Info }, { WARN => Warn }, { FATAL => Error });
 
+/// Whether `record` was logged by the wrapped library, under the module name it passed.
+pub fn is_library_record(record: &log::Record) -> bool {
+    record.module_path() == Some(module_path!()) && record.target() != module_path!()
+}
+
 pub unsafe extern "C" fn log<LEVEL: LogLevel>(
     module: *const ::std::os::raw::c_char,
     fmt: *const ::std::os::raw::c_char,
@@ -0,0 +1,169 @@
+use sdvxio_pipe_proto::{Arch, ImageFormat, LibraryImage};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Where an imported library would be loaded from.
+enum Location {
+    NextToLibrary,
+    Directory(PathBuf),
+    /// Virtual `api-ms-*` and `ext-ms-*` libraries, resolved by the system.
+    ApiSet,
+    Missing,
+    /// Not in the usual directories of an ELF image, left to the dynamic loader and its cache.
+    Unknown,
+}
+
+/// Inspects the library at `path` without loading it and writes a report to `out`, checking its
+/// exports against the module `definition` of its API.
+///
+/// Returns whether the library has this program's architecture, exports every required function
+/// and has all its imports available.
+pub fn run(
+    path: &Path,
+    definition: &str,
+    optional: &[&str],
+    out: &mut dyn Write,
+) -> std::io::Result<bool> {
+    let path = resolve(path);
+    writeln!(out, "Library: {}", path.display())?;
+    let image = match LibraryImage::read(&path) {
+        Ok(image) => image,
+        Err(err) => {
+            writeln!(out, "Cannot read the library: {}", err)?;
+            return Ok(false);
+        }
+    };
+    let mut usable = true;
+
+    let arch = image.arch;
+    if arch == Arch::current() {
+        writeln!(out, "Architecture: {}", arch)?;
+    } else {
+        writeln!(
+            out,
+            "Architecture: {}, but this program is {} and cannot load it",
+            arch,
+            Arch::current()
+        )?;
+        usable = false;
+    }
+
+    writeln!(out, "Exports:")?;
+    for name in exports(definition) {
+        let status = if image.exports.iter().any(|export| export == name) {
+            "found"
+        } else if optional.contains(&name) {
+            "missing, optional"
+        } else {
+            usable = false;
+            "MISSING"
+        };
+        writeln!(out, "  {}: {}", name, status)?;
+    }
+
+    writeln!(out, "Imports:")?;
+    let directory = path.parent().unwrap_or(Path::new("."));
+    for import in &image.imports {
+        let location = match image.format {
+            ImageFormat::Pe => locate(import, directory),
+            ImageFormat::Elf => locate_shared(import),
+        };
+        match location {
+            Location::NextToLibrary => writeln!(out, "  {}: next to the library", import)?,
+            Location::Directory(found) => writeln!(out, "  {}: {}", import, found.display())?,
+            Location::ApiSet => writeln!(out, "  {}: provided by the system", import)?,
+            Location::Missing => {
+                usable = false;
+                writeln!(out, "  {}: NOT FOUND", import)?;
+            }
+            Location::Unknown => writeln!(
+                out,
+                "  {}: not in the usual directories, the dynamic loader may still find it",
+                import
+            )?,
+        }
+    }
+
+    if usable {
+        writeln!(out, "The library can be loaded")?;
+    } else {
+        writeln!(out, "The library cannot be loaded")?;
+    }
+    Ok(usable)
+}
+
+/// Looks up a bare file name next to the program, as loading it would.
+fn resolve(path: &Path) -> PathBuf {
+    if path.components().count() == 1
+        && let Ok(program) = std::env::current_exe()
+        && let Some(directory) = program.parent()
+    {
+        return directory.join(path);
+    }
+    path.to_owned()
+}
+
+/// Functions listed by a module definition.
+fn exports(definition: &str) -> impl Iterator<Item = &str> {
+    definition
+        .lines()
+        .skip_while(|line| line.trim() != "EXPORTS")
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().next())
+}
+
+/// Follows the order in which Windows searches for the dependencies of a library loaded by path.
+fn locate(import: &str, directory: &Path) -> Location {
+    let lowercase = import.to_ascii_lowercase();
+    if lowercase.starts_with("api-ms-") || lowercase.starts_with("ext-ms-") {
+        return Location::ApiSet;
+    }
+    if directory.join(import).exists() {
+        return Location::NextToLibrary;
+    }
+
+    let system = std::env::var_os("SystemRoot").map(PathBuf::from);
+    let directories = system
+        .iter()
+        .flat_map(|root| [root.join("System32"), root.clone()])
+        .chain(std::env::current_dir())
+        .chain(
+            std::env::var_os("PATH")
+                .into_iter()
+                .flat_map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>()),
+        );
+    for directory in directories {
+        if directory.join(import).exists() {
+            return Location::Directory(directory);
+        }
+    }
+    Location::Missing
+}
+
+/// Looks for a shared object where the dynamic loader searches without reading its cache.
+fn locate_shared(import: &str) -> Location {
+    let directories = std::env::var_os("LD_LIBRARY_PATH")
+        .into_iter()
+        .flat_map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>())
+        .chain(
+            [
+                "/lib",
+                "/usr/lib",
+                "/lib64",
+                "/usr/lib64",
+                "/lib/x86_64-linux-gnu",
+                "/usr/lib/x86_64-linux-gnu",
+                "/lib/aarch64-linux-gnu",
+                "/usr/lib/aarch64-linux-gnu",
+                "/lib/i386-linux-gnu",
+                "/usr/lib/i386-linux-gnu",
+            ]
+            .map(PathBuf::from),
+        );
+    for directory in directories {
+        if directory.join(import).exists() {
+            return Location::Directory(directory);
+        }
+    }
+    Location::Unknown
+}
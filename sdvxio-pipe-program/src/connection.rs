@@ -0,0 +1,63 @@
+use sdvxio_pipe_proto::{PreSharedKey, SharedWriter, ShmRegion, ShmSide, Socket};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Parents must complete authentication within this delay, so that they cannot hold the socket.
+const AUTHENTICATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Writer for messages to the parent, shared between responses and input pushes.
+pub type Writer = SharedWriter<Box<dyn Write + Send>>;
+
+/// A connection to a parent.
+pub struct Connection {
+    pub writer: Writer,
+    pub reader: Box<dyn Read + Send>,
+    pub shm: Option<Arc<ShmRegion>>,
+}
+
+impl Connection {
+    /// Uses stdin and `stdout`, the process's stdout or a protected duplicate of it.
+    pub fn stdio(stdout: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer: SharedWriter::new(stdout),
+            reader: Box::new(std::io::stdin()),
+            shm: None,
+        }
+    }
+
+    /// Opens the shared memory file created by the parent.
+    pub fn shm(path: &Path) -> std::io::Result<Self> {
+        let region = ShmRegion::open(path)?;
+
+        // The parent keeps stdin open for as long as it wants us running
+        let watched = region.clone();
+        std::thread::spawn(move || {
+            let _ = std::io::copy(&mut std::io::stdin(), &mut std::io::sink());
+            watched.close();
+        });
+
+        Ok(Self {
+            writer: SharedWriter::new(Box::new(region.writer(ShmSide::Child))),
+            reader: Box::new(region.reader(ShmSide::Child)),
+            shm: Some(region),
+        })
+    }
+
+    /// Wraps a connected socket, first checking that the parent knows `psk` if given.
+    pub fn socket(mut socket: Socket, psk: Option<&PreSharedKey>) -> std::io::Result<Self> {
+        if let Some(psk) = psk {
+            socket.set_read_timeout(Some(AUTHENTICATION_TIMEOUT))?;
+            psk.authenticate_child(&mut socket)?;
+            socket.set_read_timeout(None)?;
+        }
+
+        let reader = socket.try_clone()?;
+        Ok(Self {
+            writer: SharedWriter::new(Box::new(socket)),
+            reader: Box::new(reader),
+            shm: None,
+        })
+    }
+}
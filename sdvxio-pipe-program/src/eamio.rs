@@ -0,0 +1,68 @@
+//! The `eamio` API, served by an [`EamIoBackend`].
+
+use crate::bt5api::{self, FATAL, INFO, MISC, WARN};
+use crate::lanes::Lane;
+use crate::service::{Context, Service};
+use sdvxio_pipe_proto::Capabilities;
+use sdvxio_pipe_proto::eamio::{Call, Eamio, Request, Response, Return};
+use std::path::Path;
+
+mod backend;
+mod library;
+
+pub use backend::{EamIoBackend, Library};
+
+/// Serves `eamio` with a backend.
+pub struct EamioService<B> {
+    backend: B,
+}
+
+/// Loads the wrapped `eamio` library, which logs through this program's logger.
+pub fn load(path: &Path) -> Result<EamioService<Library>, String> {
+    library::load(path)?;
+    unsafe {
+        library::eam_io_set_loggers(
+            Some(bt5api::log::<MISC>),
+            Some(bt5api::log::<INFO>),
+            Some(bt5api::log::<WARN>),
+            Some(bt5api::log::<FATAL>),
+        );
+    };
+    Ok(EamioService::new(Library))
+}
+
+impl<B: EamIoBackend> EamioService<B> {
+    pub fn new(backend: B) -> Self {
+        Self { backend }
+    }
+}
+
+impl<B: EamIoBackend> Service for EamioService<B> {
+    type Api = Eamio;
+    const DEFINITION: &'static str = include_str!("../eamio.def");
+    const OPTIONAL: &'static [&'static str] = library::OPTIONAL;
+
+    fn capabilities(&self) -> Capabilities {
+        self.backend.capabilities()
+    }
+
+    fn call(&self, call: Call) -> Result<Return, String> {
+        Ok(backend::call(&self.backend, call))
+    }
+
+    fn request(&self, request: &Request, _context: &Context) -> Result<Response, String> {
+        let response = match *request {
+            Request::ReadCard { unit_no } => {
+                let (card_type, card_id) = self.backend.read_card(unit_no);
+                Response::ReadCard { card_type, card_id }
+            }
+        };
+        Ok(response)
+    }
+
+    fn lane(request: &Request) -> Option<Lane> {
+        match request {
+            Request::ReadCard { .. } => Some(Lane::Input),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use super::library;
+use crate::bt5api;
+use sdvxio_pipe_proto::Capabilities;
+use sdvxio_pipe_proto::eamio::{CARD_ID_SIZE, Call, Eamio, Return};
+
+/// An `eamio` implementation serving the parent's requests.
+///
+/// Methods are never called concurrently with each other, as every `eamio` function is an input
+/// one, see [`bt5api::lock_input`].
+pub trait EamIoBackend: Send + Sync + 'static {
+    fn init(&self) -> bool;
+    fn fini(&self);
+    fn get_keypad_state(&self, unit_no: u8) -> u16;
+    fn get_sensor_state(&self, unit_no: u8) -> u8;
+    /// Returns the type of the card in the slot and its ID.
+    fn read_card(&self, unit_no: u8) -> (u8, [u8; CARD_ID_SIZE]);
+    fn card_slot_cmd(&self, unit_no: u8, cmd: u8) -> bool;
+    fn poll(&self, unit_no: u8) -> bool;
+
+    /// Capabilities of this build the backend supports, advertised to the parent.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::supported::<Eamio>()
+    }
+}
+
+/// Generates [`call`], serving each function with the backend method of the same name.
+macro_rules! dispatch {
+    ($(
+        $kind:ident {
+            $($variant:ident = $export:ident as $method:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*
+        }
+    )*) => {
+        /// Calls the backend method serving `call`.
+        pub fn call<B: EamIoBackend>(backend: &B, call: Call) -> Return {
+            match call {
+                $($(
+                    Call::$variant { $($arg),* } => Return::$variant(backend.$method($($arg),*)),
+                )*)*
+            }
+        }
+    };
+}
+
+sdvxio_pipe_proto::eamio_api!(dispatch);
+
+/// The wrapped library, once loaded by [`library::load`].
+pub struct Library;
+
+impl EamIoBackend for Library {
+    fn init(&self) -> bool {
+        unsafe {
+            library::eam_io_init(
+                Some(bt5api::create_thread::<Eamio>),
+                Some(bt5api::join_thread),
+                Some(bt5api::destroy_thread),
+            )
+        }
+    }
+
+    fn fini(&self) {
+        unsafe { library::eam_io_fini() }
+    }
+
+    fn get_keypad_state(&self, unit_no: u8) -> u16 {
+        unsafe { library::eam_io_get_keypad_state(unit_no) }
+    }
+
+    fn get_sensor_state(&self, unit_no: u8) -> u8 {
+        unsafe { library::eam_io_get_sensor_state(unit_no) }
+    }
+
+    fn read_card(&self, unit_no: u8) -> (u8, [u8; CARD_ID_SIZE]) {
+        let mut card_id = [0u8; CARD_ID_SIZE];
+        let card_type =
+            unsafe { library::eam_io_read_card(unit_no, card_id.as_mut_ptr(), CARD_ID_SIZE as u8) };
+        (card_type, card_id)
+    }
+
+    fn card_slot_cmd(&self, unit_no: u8, cmd: u8) -> bool {
+        unsafe { library::eam_io_card_slot_cmd(unit_no, cmd) }
+    }
+
+    fn poll(&self, unit_no: u8) -> bool {
+        unsafe { library::eam_io_poll(unit_no) }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::supported::<Eamio>().difference(library::missing_capabilities())
+    }
+}
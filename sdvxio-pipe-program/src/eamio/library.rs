@@ -0,0 +1,24 @@
+use crate::bt5api::{log_formatter_t, thread_create_t, thread_destroy_t, thread_join_t};
+
+crate::bt5api::library! {
+    required {
+        fn eam_io_set_loggers(
+            misc: log_formatter_t,
+            info: log_formatter_t,
+            warning: log_formatter_t,
+            fatal: log_formatter_t
+        );
+        fn eam_io_init(
+            thread_create: thread_create_t,
+            thread_join: thread_join_t,
+            thread_destroy: thread_destroy_t
+        ) -> bool;
+        fn eam_io_fini();
+        fn eam_io_get_keypad_state(unit_no: u8) -> u16;
+        fn eam_io_get_sensor_state(unit_no: u8) -> u8;
+        fn eam_io_read_card(unit_no: u8, card_id: *mut u8, nbytes: u8) -> u8;
+        fn eam_io_card_slot_cmd(unit_no: u8, cmd: u8) -> bool;
+        fn eam_io_poll(unit_no: u8) -> bool;
+    }
+    optional {}
+}
@@ -0,0 +1,103 @@
+use crate::connection::Writer;
+use crate::service::Service;
+use crate::{State, bt5api, handle_message, log};
+use sdvxio_pipe_proto::{Api, ApiCall, CallKind, ChildToParent, Message, ParentToChild, Sender};
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+/// Requests served concurrently with those of the other lane, as the BT5 API lets games drive
+/// input and output from different threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    Input,
+    Output,
+}
+
+enum Job<A: Api> {
+    Request(Message<ParentToChild<A>>),
+    /// Acknowledged once every request submitted before it was handled.
+    Barrier(mpsc::SyncSender<()>),
+}
+
+/// Handles the requests of one lane, in order, on its own thread.
+pub struct Worker<A: Api> {
+    jobs: mpsc::Sender<Job<A>>,
+    thread: JoinHandle<()>,
+}
+
+impl Lane {
+    /// Lane of a request, or `None` for requests that must run alone, in order with all others.
+    pub fn of<S: Service>(msg: &ParentToChild<S::Api>) -> Option<Self> {
+        match msg {
+            ParentToChild::Call(call) => match call.kind() {
+                CallKind::Input => Some(Lane::Input),
+                CallKind::Output => Some(Lane::Output),
+                CallKind::Lifecycle => None,
+            },
+            ParentToChild::Request(request) => S::lane(request),
+            ParentToChild::Hello(_) | ParentToChild::SetLogLevel(_) => None,
+        }
+    }
+
+    fn thread_name(self, api: &str) -> String {
+        match self {
+            Lane::Input => format!("{}-pipe-input-lane", api),
+            Lane::Output => format!("{}-pipe-output-lane", api),
+        }
+    }
+}
+
+impl<A: Api> Worker<A> {
+    pub fn spawn<S: Service<Api = A>>(lane: Lane, state: Arc<State<S>>) -> std::io::Result<Self> {
+        let (jobs, receiver) = mpsc::channel();
+        let tx = Sender::new(state.context.writer.clone());
+        let thread = std::thread::Builder::new()
+            .name(lane.thread_name(A::NAME))
+            .spawn(move || serve(lane, &state, tx, receiver))?;
+        Ok(Self { jobs, thread })
+    }
+
+    /// Queues a request, returning `false` if the worker stopped after failing to respond.
+    pub fn submit(&self, msg: Message<ParentToChild<A>>) -> bool {
+        self.jobs.send(Job::Request(msg)).is_ok()
+    }
+
+    /// Waits for the requests submitted so far to be handled.
+    pub fn wait_idle(&self) {
+        let (done, idle) = mpsc::sync_channel(1);
+        if self.jobs.send(Job::Barrier(done)).is_ok() {
+            let _ = idle.recv();
+        }
+    }
+
+    /// Handles the remaining requests, then stops the worker.
+    pub fn stop(self) {
+        drop(self.jobs);
+        let _ = self.thread.join();
+    }
+}
+
+fn serve<S: Service>(
+    lane: Lane,
+    state: &State<S>,
+    mut tx: Sender<Writer, Message<ChildToParent<S::Api>>>,
+    jobs: mpsc::Receiver<Job<S::Api>>,
+) {
+    for job in jobs {
+        let msg = match job {
+            Job::Request(msg) => msg,
+            Job::Barrier(done) => {
+                let _ = done.send(());
+                continue;
+            }
+        };
+
+        // The input stream polls the library too
+        let _input = (lane == Lane::Input).then(bt5api::lock_input);
+        if let Err(err) = handle_message(state, &mut tx, msg) {
+            log::warn!("Failed to send response: {}", err);
+            break;
+        }
+    }
+}
@@ -1,25 +1,51 @@
+use crate::bt5api;
+use crate::connection::Writer;
 use anstyle::Style;
+use sdvxio_pipe_proto::{Api, ChildToParent, LogLevel, Message, Sender};
 use std::fmt;
 use std::fs::File;
 use std::io::Write;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::mpsc::SyncSender;
+use std::sync::{Mutex, OnceLock};
 
 pub use log::*;
 
+/// Records waiting to be forwarded, dropped beyond this so that logging never blocks.
+const FORWARD_QUEUE: usize = 256;
+
+/// Most verbose level forwarded to the parent, as a `log::Level`, or `0` when not forwarding.
+static FORWARD_LEVEL: AtomicU8 = AtomicU8::new(0);
+static FORWARD: Mutex<Option<SyncSender<Forwarded>>> = Mutex::new(None);
+static FILE_LEVEL: OnceLock<LevelFilter> = OnceLock::new();
+
+/// Writes records to the log file, and forwards them to the parent once it asked for them.
+struct ForwardingLogger {
+    file: env_logger::Logger,
+}
+
+/// A record waiting to be forwarded, whatever the API of the parent.
+struct Forwarded {
+    level: LogLevel,
+    target: String,
+    message: String,
+}
+
 #[derive(Debug)]
 pub struct Logger {
     file: File,
 }
 
 impl Logger {
-    pub fn new() -> Self {
+    /// Logs to `<api>-pipe.log`.
+    pub fn new(api: &str) -> Self {
         Self {
-            file: File::create("sdvxio-pipe.log").unwrap(),
+            file: File::create(format!("{}-pipe.log", api)).unwrap(),
         }
     }
 
     pub fn init(self) {
-        env_logger::builder()
+        let file = env_logger::builder()
             .filter_level(LevelFilter::Trace)
             .filter_module(
                 "sdvxio_pipe_program",
@@ -32,7 +58,7 @@ impl Logger {
             .parse_default_env()
             .target(env_logger::Target::Pipe(Box::new(self)))
             .format(|f, record| {
-                use crate::log::{colored_level, max_target_width, Padded};
+                use crate::log::{Padded, colored_level, max_target_width};
                 use std::io::Write;
 
                 let target = record.target();
@@ -52,7 +78,11 @@ impl Logger {
 
                 writeln!(f, "[{}] {} {} -> {}", time, level, target, record.args())
             })
-            .init();
+            .build();
+
+        let _ = FILE_LEVEL.set(file.filter());
+        log::set_max_level(file.filter());
+        log::set_boxed_logger(Box::new(ForwardingLogger { file })).expect("failed to set logger");
     }
 }
 
@@ -66,6 +96,91 @@ impl Write for Logger {
     }
 }
 
+impl Log for ForwardingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.file.enabled(metadata) || forwarded(metadata.level())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.file.matches(record) {
+            self.file.log(record);
+        }
+        if forwarded(record.level()) {
+            let forward = FORWARD.lock().expect("failed to lock log forwarding");
+            if let Some(forward) = forward.as_ref() {
+                // Errors of the wrapped library stay fatal for the game, as they would be
+                // in-process, while the program's own errors are recoverable
+                let level = match record.level() {
+                    Level::Error if !bt5api::is_library_record(record) => Level::Warn,
+                    level => level,
+                };
+                // Dropped rather than blocking the caller when the forwarder falls behind
+                let _ = forward.try_send(Forwarded {
+                    level: level.into(),
+                    target: record.target().to_owned(),
+                    message: record.args().to_string(),
+                });
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.file.flush();
+    }
+}
+
+fn forwarded(level: Level) -> bool {
+    level as u8 <= FORWARD_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Forwards records up to `level` to the parent through `writer`, or stops forwarding with `None`.
+pub fn forward<A: Api>(writer: &Writer, level: Option<LogLevel>) {
+    let Some(level) = level else {
+        stop_forwarding();
+        return;
+    };
+
+    let (sender, receiver) = std::sync::mpsc::sync_channel::<Forwarded>(FORWARD_QUEUE);
+    let mut tx = Sender::new(writer.clone());
+    // Must not log, as its own records would be forwarded back to it
+    let spawned = std::thread::Builder::new()
+        .name(format!("{}-pipe-log", A::NAME))
+        .spawn(move || {
+            for record in receiver {
+                let payload =
+                    ChildToParent::<A>::log(record.level, &record.target, &record.message);
+                if tx.send(&Message::push(payload)).is_err() {
+                    break;
+                }
+            }
+        });
+    if let Err(err) = spawned {
+        stop_forwarding();
+        log::warn!("Failed to start log forwarding thread: {}", err);
+        return;
+    }
+
+    // Replacing the previous sender ends its thread once its queue is drained
+    *FORWARD.lock().expect("failed to lock log forwarding") = Some(sender);
+    FORWARD_LEVEL.store(Level::from(level) as u8, Ordering::Relaxed);
+    update_max_level();
+}
+
+pub fn stop_forwarding() {
+    FORWARD_LEVEL.store(0, Ordering::Relaxed);
+    *FORWARD.lock().expect("failed to lock log forwarding") = None;
+    update_max_level();
+}
+
+fn update_max_level() {
+    let file = FILE_LEVEL.get().copied().unwrap_or(LevelFilter::Off);
+    let forward = FORWARD_LEVEL.load(Ordering::Relaxed);
+    let forward = LevelFilter::iter()
+        .find(|filter| *filter as u8 == forward)
+        .unwrap_or(LevelFilter::Off);
+    log::set_max_level(file.max(forward));
+}
+
 pub(crate) struct Styled<T> {
     pub(crate) style: Style,
     pub(crate) item: T,
@@ -119,3 +234,86 @@ pub(crate) fn colored_level(style: Style, level: Level) -> Styled<&'static str>
         Level::Error => "ERROR".styled(style.fg_color(Some(anstyle::AnsiColor::Red.into()))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdvxio_pipe_proto::sdvxio::Sdvxio;
+    use sdvxio_pipe_proto::{Receiver, SharedWriter};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    /// What the forwarding thread wrote.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Captured {
+        fn records(&self) -> Vec<(LogLevel, String, String)> {
+            let bytes = self.0.lock().unwrap().clone();
+            let mut rx = Receiver::<_, Message<ChildToParent<Sdvxio>>>::new(bytes.as_slice());
+            let mut records = Vec::new();
+            while let Ok(message) = rx.recv() {
+                if let ChildToParent::Log {
+                    level,
+                    target,
+                    message,
+                } = message.payload
+                {
+                    records.push((level, target, message));
+                }
+            }
+            records
+        }
+    }
+
+    #[test]
+    fn forwards_records_up_to_the_level() {
+        let logger = ForwardingLogger {
+            file: env_logger::builder()
+                .filter_level(LevelFilter::Off)
+                .target(env_logger::Target::Pipe(Box::new(std::io::sink())))
+                .build(),
+        };
+        let captured = Captured::default();
+        forward::<Sdvxio>(
+            &SharedWriter::new(Box::new(captured.clone())),
+            Some(LogLevel::Info),
+        );
+        for (level, message) in [
+            (Level::Debug, "debug"),
+            (Level::Info, "info"),
+            (Level::Error, "error"),
+        ] {
+            logger.log(
+                &Record::builder()
+                    .level(level)
+                    .target("module")
+                    .args(format_args!("{}", message))
+                    .build(),
+            );
+        }
+        stop_forwarding();
+
+        // The program's own errors are only warnings for the game
+        let expected = [
+            (LogLevel::Info, "module".to_owned(), "info".to_owned()),
+            (LogLevel::Warn, "module".to_owned(), "error".to_owned()),
+        ];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while captured.records().len() < expected.len() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(captured.records(), expected);
+    }
+}
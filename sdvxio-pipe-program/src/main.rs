@@ -1,101 +1,346 @@
 #![feature(c_variadic)]
 
-use crate::bt5api::{FATAL, INFO, MISC, WARN};
-use sdvxio_pipe_proto::{ChildToParent, Message, ParentToChild, Receiver, Sender};
-use std::io::Stdout;
+use crate::args::Args;
+use crate::connection::{Connection, Writer};
+use crate::lanes::{Lane, Worker};
+use crate::service::{CallOf, Context, ReturnOf, Service};
+use sdvxio_pipe_proto::eamio::Eamio;
+use sdvxio_pipe_proto::sdvxio::Sdvxio;
+use sdvxio_pipe_proto::{
+    Api, ChildErrorKind, ChildToParent, CorruptFrame, Hello, Message, PROTOCOL_VERSION,
+    ParentToChild, Receiver, Sender,
+};
+use std::any::Any;
+use std::io::Write;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+mod args;
 mod bt5api;
+mod check;
+mod connection;
+mod eamio;
+mod lanes;
 mod log;
+mod sdvxio;
+mod service;
+mod stdio;
 
 fn main() {
-    log::Logger::new().init();
+    let args = Args::parse();
+    let api = args.as_ref().map_or(Sdvxio::NAME, Args::api);
+    log::Logger::new(api).init();
     panic_log::initialize_hook(panic_log::Configuration::default());
-    unsafe {
-        bt5api::sdvx_io_set_loggers(
-            Some(bt5api::log::<MISC>),
-            Some(bt5api::log::<INFO>),
-            Some(bt5api::log::<WARN>),
-            Some(bt5api::log::<FATAL>),
-        );
+
+    log::info!("Starting sdvxio-pipe-program for {}", api);
+
+    // Kept for the whole run even when it is not the protocol channel, as the parent may watch it
+    let stdout: Box<dyn Write + Send> = match stdio::protect(api) {
+        Ok(stdout) => Box::new(stdout),
+        Err(err) => {
+            log::warn!("Failed to protect stdout from the library: {}", err);
+            Box::new(std::io::stdout())
+        }
+    };
+
+    let args = match args {
+        Ok(args) => args,
+        Err(err) => {
+            log::error!("Invalid arguments: {}", err);
+            std::process::exit(1);
+        }
+    };
+    for arg in &args.unknown {
+        log::warn!("Ignoring unknown argument {:?}", arg);
+    }
+
+    match args.api() {
+        Sdvxio::NAME => run(&args, stdout, sdvxio::load),
+        Eamio::NAME => run(&args, stdout, eamio::load),
+        api => {
+            log::error!("Unknown API {}", api);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Loads the wrapped library with `load` and serves its API to parents.
+fn run<S: Service>(
+    args: &Args,
+    mut stdout: Box<dyn Write + Send>,
+    load: fn(&Path) -> Result<S, String>,
+) {
+    let library = args
+        .library
+        .clone()
+        .unwrap_or_else(|| bt5api::default_library(S::Api::NAME));
+    if args.check {
+        let usable =
+            check::run(&library, S::DEFINITION, S::OPTIONAL, &mut stdout).unwrap_or_else(|err| {
+                log::error!("Failed to write the report: {}", err);
+                false
+            });
+        std::process::exit(if usable { 0 } else { 1 });
+    }
+    let service = match load(&library) {
+        Ok(service) => Arc::new(service),
+        Err(err) => {
+            log::error!(
+                "Failed to load the wrapped library, run with --check for details: {}",
+                err
+            );
+            std::process::exit(1);
+        }
     };
+    log::info!("Loaded {}", library.display());
 
-    log::info!("Starting sdvxio-pipe-program");
+    let psk = match args.psk(S::Api::NAME) {
+        Ok(psk) => psk,
+        Err(err) => {
+            log::error!("Invalid pre-shared key: {}", err);
+            std::process::exit(1);
+        }
+    };
+    if psk.is_some() && args.connect.is_none() && args.listen.is_none() {
+        log::warn!("The pre-shared key is only used by socket connections");
+    }
 
-    let mut tx = Sender::new(std::io::stdout());
-    let mut rx = Receiver::<_, Message<ParentToChild>>::new(std::io::stdin());
+    if let Some(endpoint) = &args.listen {
+        let listener = match endpoint.bind() {
+            Ok(listener) => listener,
+            Err(err) => {
+                log::error!("Failed to listen on {}: {}", endpoint, err);
+                std::process::exit(1);
+            }
+        };
+        log::info!("Listening on {}", endpoint);
+        loop {
+            let socket = match listener.accept() {
+                Ok(socket) => socket,
+                Err(err) => {
+                    log::warn!("Failed to accept a connection: {}", err);
+                    continue;
+                }
+            };
+            let peer = socket.peer();
+            match Connection::socket(socket, psk.as_ref()) {
+                Ok(connection) => {
+                    log::info!("Parent connected from {}", peer);
+                    serve(connection, &service, args.serial);
+                    if args.once {
+                        return;
+                    }
+                }
+                Err(err) => log::warn!("Rejected connection from {}: {}", peer, err),
+            }
+        }
+    }
+
+    let connection = match (&args.shm, &args.connect) {
+        (Some(path), _) => Connection::shm(path).inspect(|_| {
+            log::info!("Using shared memory {}", path.display());
+        }),
+        (_, Some(endpoint)) => endpoint
+            .connect(None)
+            .and_then(|socket| Connection::socket(socket, psk.as_ref()))
+            .inspect(|_| log::info!("Connected to {}", endpoint)),
+        (None, None) => Ok(Connection::stdio(stdout)),
+    };
+    match connection {
+        Ok(connection) => serve(connection, &service, args.serial),
+        Err(err) => {
+            log::error!("Failed to connect to the parent: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handles requests until the parent finalizes the library or disconnects.
+///
+/// Input and output requests are handled on one thread each, unless `serial` is set. Other
+/// requests wait for both to be idle and run alone.
+fn serve<S: Service>(connection: Connection, service: &Arc<S>, serial: bool) {
+    let state = Arc::new(State {
+        service: service.clone(),
+        context: Context {
+            writer: connection.writer.clone(),
+            shm: connection.shm.clone(),
+        },
+        initialized: AtomicBool::new(false),
+        finalized: AtomicBool::new(false),
+    });
+    let mut tx = Sender::new(connection.writer.clone());
+    let mut rx = Receiver::<_, Message<ParentToChild<S::Api>>>::new(connection.reader);
+
+    let workers = if serial {
+        Vec::new()
+    } else {
+        let workers = [Lane::Input, Lane::Output]
+            .into_iter()
+            .map(|lane| Worker::spawn(lane, state.clone()).map(|worker| (lane, worker)))
+            .collect::<std::io::Result<Vec<_>>>();
+        workers.unwrap_or_else(|err| {
+            log::warn!(
+                "Failed to start lane threads, handling requests serially: {}",
+                err
+            );
+            Vec::new()
+        })
+    };
 
     log::info!("Starting main loop");
-    while let Ok(msg) = rx.recv() {
-        handle_message(&mut tx, msg);
+    loop {
+        let msg = match rx.recv() {
+            Ok(msg) => msg,
+            Err(err) => match CorruptFrame::from_io(&err) {
+                // The parent times out waiting for the response and carries on
+                Some(frame) => {
+                    log::warn!("Discarded {} corrupted bytes from parent", frame.discarded);
+                    continue;
+                }
+                None => break,
+            },
+        };
+
+        let lane = Lane::of::<S>(&msg.payload);
+        if let Some((_, worker)) = workers.iter().find(|(other, _)| Some(*other) == lane) {
+            if !worker.submit(msg) {
+                break;
+            }
+            continue;
+        }
+
+        for (_, worker) in &workers {
+            worker.wait_idle();
+        }
+        if matches!(msg.payload, ParentToChild::Call(call) if call == S::Api::FINI) {
+            // What the service runs may call the library, so it must stop before it shuts down
+            state.service.stop();
+        }
+        let _input = bt5api::lock_input();
+        if let Err(err) = handle_message(&state, &mut tx, msg) {
+            log::warn!("Failed to send response: {}", err);
+            break;
+        }
+        if state.finalized.load(Ordering::Acquire) {
+            break;
+        }
+    }
+
+    if state.finalized.load(Ordering::Acquire) {
+        log::info!("Parent finalized the library");
+    } else {
+        log::info!("Parent disconnected");
+    }
+    log::stop_forwarding();
+    for (_, worker) in workers {
+        worker.stop();
+    }
+    state.service.stop();
+    if state.initialized.load(Ordering::Acquire) {
+        // The next parent initializes the library again
+        let _input = bt5api::lock_input();
+        if let Err(err) = state.service.call(S::Api::FINI) {
+            log::warn!("Failed to finalize the library: {}", err);
+        }
+    }
+    if let Some(shm) = &connection.shm {
+        shm.close();
     }
 }
 
-fn handle_message(tx: &mut Sender<Stdout, Message<ChildToParent>>, msg: Message<ParentToChild>) {
-    match msg.payload {
-        ParentToChild::InitRequest => {
-            let success = unsafe {
-                bt5api::sdvx_io_init(
-                    Some(bt5api::create_thread),
-                    Some(bt5api::join_thread),
-                    Some(bt5api::destroy_thread),
-                )
-            };
-            tx.send(&msg.reply(ChildToParent::InitResponse(success)))
-                .expect("failed to send response");
-        }
-        ParentToChild::FinalizeRequest => {
-            unsafe { bt5api::sdvx_io_fini() };
-            tx.send(&msg.reply(ChildToParent::FinalizeResponse))
-                .expect("failed to send response");
-            std::thread::sleep(std::time::Duration::from_secs(1));
-            std::process::exit(0);
-        }
-        ParentToChild::SetGpioLightsRequest(lights) => {
-            unsafe { bt5api::sdvx_io_set_gpio_lights(lights) };
-            tx.send(&msg.reply(ChildToParent::SetGpioLightsResponse))
-                .expect("failed to send response");
-        }
-        ParentToChild::SetPwmLightRequest {
-            light_no,
-            intensity,
-        } => {
-            unsafe { bt5api::sdvx_io_set_pwm_light(light_no, intensity) };
-            tx.send(&msg.reply(ChildToParent::SetPwmLightResponse))
-                .expect("failed to send response");
-        }
-        ParentToChild::WriteOutputRequest => {
-            let result = unsafe { bt5api::sdvx_io_write_output() };
-            tx.send(&msg.reply(ChildToParent::WriteOutputResponse(result)))
-                .expect("failed to send response");
-        }
-        ParentToChild::ReadInputRequest => {
-            let result = unsafe { bt5api::sdvx_io_read_input() };
-            tx.send(&msg.reply(ChildToParent::ReadInputResponse(result)))
-                .expect("failed to send response");
-        }
-        ParentToChild::GetInputGpioSysRequest => {
-            let result = unsafe { bt5api::sdvx_io_get_input_gpio_sys() };
-            tx.send(&msg.reply(ChildToParent::GetInputGpioSysResponse(result)))
-                .expect("failed to send response");
-        }
-        ParentToChild::GetInputGpioRequest(bank) => {
-            let result = unsafe { bt5api::sdvx_io_get_input_gpio(bank) };
-            tx.send(&msg.reply(ChildToParent::GetInputGpioResponse(result)))
-                .expect("failed to send response");
-        }
-        ParentToChild::GetSpinnerPosRequest(spinner) => {
-            let result = unsafe { bt5api::sdvx_io_get_spinner_pos(spinner) };
-            tx.send(&msg.reply(ChildToParent::GetSpinnerPosResponse(result)))
-                .expect("failed to send response");
-        }
-        ParentToChild::SetAmpVolumeRequest {
-            primary,
-            headphone,
-            subwoofer,
-        } => {
-            let result = unsafe { bt5api::sdvx_io_set_amp_volume(primary, headphone, subwoofer) };
-            tx.send(&msg.reply(ChildToParent::SetAmpVolumeResponse(result)))
-                .expect("failed to send response");
+/// Shared by the main loop and the lane threads.
+struct State<S> {
+    service: Arc<S>,
+    context: Context,
+    initialized: AtomicBool,
+    finalized: AtomicBool,
+}
+
+/// Serves a request and sends its response, or an `Error` if it failed or panicked.
+fn handle_message<S: Service>(
+    state: &State<S>,
+    tx: &mut Sender<Writer, Message<ChildToParent<S::Api>>>,
+    msg: Message<ParentToChild<S::Api>>,
+) -> std::io::Result<()> {
+    let response = match std::panic::catch_unwind(AssertUnwindSafe(|| respond(state, &msg.payload)))
+    {
+        Ok(Ok(response)) => response,
+        Ok(Err(message)) => {
+            log::warn!("Failed to handle {:?}: {}", msg.payload, message);
+            ChildToParent::error(msg.id, ChildErrorKind::Failed, &message)
+        }
+        Err(panic) => {
+            let message = panic_message(panic.as_ref());
+            log::warn!("Panicked while handling {:?}: {}", msg.payload, message);
+            ChildToParent::error(msg.id, ChildErrorKind::Panicked, &message)
+        }
+    };
+    tx.send(&msg.reply(response))
+}
+
+fn respond<S: Service>(
+    state: &State<S>,
+    msg: &ParentToChild<S::Api>,
+) -> Result<ChildToParent<S::Api>, String> {
+    let response = match msg {
+        ParentToChild::Hello(hello) => {
+            log::info!(
+                "Parent {}-pipe {} ({}), protocol version {}",
+                S::Api::NAME,
+                hello.version,
+                hello.arch,
+                hello.protocol_version
+            );
+            if hello.protocol_version != PROTOCOL_VERSION {
+                log::error!(
+                    "Protocol version mismatch: parent uses {}, we use {}",
+                    hello.protocol_version,
+                    PROTOCOL_VERSION
+                );
+            }
+            ChildToParent::HelloResponse(Hello {
+                capabilities: state.service.capabilities(),
+                ..Hello::current::<S::Api>()
+            })
+        }
+        ParentToChild::Call(call) => {
+            let value = state.service.call(*call)?;
+            state.record(call, &value);
+            ChildToParent::Return(value)
+        }
+        ParentToChild::Request(request) => {
+            ChildToParent::Response(state.service.request(request, &state.context)?)
+        }
+        ParentToChild::SetLogLevel(level) => {
+            log::forward::<S::Api>(&state.context.writer, *level);
+            ChildToParent::SetLogLevelResponse
+        }
+    };
+    Ok(response)
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => (*message).to_owned(),
+        None => match panic.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic".to_owned(),
+        },
+    }
+}
+
+impl<S: Service> State<S> {
+    /// Keeps track of whether the library is initialized.
+    fn record(&self, call: &CallOf<S>, value: &ReturnOf<S>) {
+        if *call == S::Api::INIT {
+            let success = S::Api::initialized(value);
+            self.initialized.store(success, Ordering::Release);
+        } else if *call == S::Api::FINI {
+            self.initialized.store(false, Ordering::Release);
+            self.finalized.store(true, Ordering::Release);
         }
     }
 }
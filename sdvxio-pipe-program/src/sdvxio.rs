@@ -0,0 +1,301 @@
+//! The `sdvxio` API, served by an [`SdvxIoBackend`].
+
+use crate::bt5api::{self, FATAL, INFO, MISC, WARN};
+use crate::lanes::Lane;
+use crate::service::{Context, Service};
+use sdvxio_pipe_proto::sdvxio::{Call, LightState, Request, Response, Return, Sdvxio};
+use sdvxio_pipe_proto::{Capabilities, Sender};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+mod backend;
+mod library;
+#[cfg(test)]
+mod mock;
+mod stream;
+
+pub use backend::{Library, SdvxIoBackend};
+
+/// Serves `sdvxio` with a backend, applying output frames and streaming input.
+pub struct SdvxioService<B> {
+    backend: Arc<B>,
+    /// Lights as last set on the backend, so that output frames only apply changes.
+    applied_lights: Mutex<LightState>,
+}
+
+/// Loads the wrapped `sdvxio` library, which logs through this program's logger.
+pub fn load(path: &Path) -> Result<SdvxioService<Library>, String> {
+    library::load(path)?;
+    unsafe {
+        library::sdvx_io_set_loggers(
+            Some(bt5api::log::<MISC>),
+            Some(bt5api::log::<INFO>),
+            Some(bt5api::log::<WARN>),
+            Some(bt5api::log::<FATAL>),
+        );
+    };
+    Ok(SdvxioService::new(Library))
+}
+
+impl<B: SdvxIoBackend> SdvxioService<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            applied_lights: Mutex::new(LightState::default()),
+        }
+    }
+
+    /// A panic while applying lights poisons the lock, the lights as last recorded still apply.
+    fn applied_lights(&self) -> MutexGuard<'_, LightState> {
+        self.applied_lights
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<B: SdvxIoBackend> Service for SdvxioService<B> {
+    type Api = Sdvxio;
+    const DEFINITION: &'static str = include_str!("../sdvxio.def");
+    const OPTIONAL: &'static [&'static str] = library::OPTIONAL;
+
+    fn capabilities(&self) -> Capabilities {
+        self.backend.capabilities()
+    }
+
+    fn call(&self, call: Call) -> Result<Return, String> {
+        let value = backend::call(&*self.backend, call)?;
+        match call {
+            Call::SetGpioLights { gpio_lights } => {
+                self.applied_lights().gpio_lights = Some(gpio_lights);
+            }
+            Call::SetPwmLight {
+                light_no,
+                intensity,
+            } => {
+                if let Some(light) = self.applied_lights().pwm_lights.get_mut(light_no as usize) {
+                    *light = Some(intensity);
+                }
+            }
+            _ => {}
+        }
+        Ok(value)
+    }
+
+    fn request(&self, request: &Request, context: &Context) -> Result<Response, String> {
+        let response = match *request {
+            Request::ReadInputSnapshot => {
+                let (success, snapshot) = self.backend.read_input_snapshot();
+                Response::ReadInputSnapshot { success, snapshot }
+            }
+            Request::StartInputStream { rate_hz } => {
+                let sink = match &context.shm {
+                    Some(region) => stream::Sink::Shm(region.clone()),
+                    None => stream::Sink::Push(Sender::new(context.writer.clone())),
+                };
+                stream::start(rate_hz, sink, self.backend.clone())?;
+                Response::StartInputStream(true)
+            }
+            Request::WriteOutputFrame(ref frame) => {
+                let mut applied_lights = self.applied_lights();
+                let mut lights = *applied_lights;
+                lights.set(frame);
+                // With shared memory, the parent publishes the whole light state instead
+                if let Some(published) = context.shm.as_ref().and_then(|shm| shm.read_output()) {
+                    lights = published;
+                }
+                let changes = lights.changes_since(&applied_lights);
+                *applied_lights = lights;
+                drop(applied_lights);
+
+                if let Some(lights) = changes.gpio_lights {
+                    self.backend.set_gpio_lights(lights);
+                }
+                for &(light_no, intensity) in &changes.pwm_lights {
+                    self.backend.set_pwm_light(light_no, intensity);
+                }
+                Response::WriteOutputFrame(self.backend.write_output())
+            }
+        };
+        Ok(response)
+    }
+
+    fn lane(request: &Request) -> Option<Lane> {
+        match request {
+            Request::ReadInputSnapshot => Some(Lane::Input),
+            Request::WriteOutputFrame(_) => Some(Lane::Output),
+            Request::StartInputStream { .. } => None,
+        }
+    }
+
+    /// Stops the input stream, which polls the library. The next parent starts with no lights
+    /// applied.
+    fn stop(&self) {
+        stream::stop();
+        *self.applied_lights() = LightState::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockBackend;
+    use sdvxio_pipe_proto::SharedWriter;
+    use sdvxio_pipe_proto::sdvxio::{InputSnapshot, OutputFrame};
+    use std::time::{Duration, Instant};
+
+    fn context() -> Context {
+        Context {
+            writer: SharedWriter::new(Box::new(std::io::sink())),
+            shm: None,
+        }
+    }
+
+    #[test]
+    fn reads_an_input_snapshot() {
+        let service = SdvxioService::new(MockBackend::new(true));
+        let response = service.request(&Request::ReadInputSnapshot, &context());
+        assert_eq!(
+            response,
+            Ok(Response::ReadInputSnapshot {
+                success: true,
+                snapshot: InputSnapshot {
+                    gpio_sys: MockBackend::GPIO_SYS,
+                    gpio: [MockBackend::gpio(0), MockBackend::gpio(1)],
+                    spinners: [MockBackend::spinner_pos(0), MockBackend::spinner_pos(1)],
+                },
+            })
+        );
+        assert_eq!(
+            service.backend.take_calls(),
+            [
+                Call::ReadInput {},
+                Call::GetInputGpioSys {},
+                Call::GetInputGpio { gpio_bank: 0 },
+                Call::GetInputGpio { gpio_bank: 1 },
+                Call::GetSpinnerPos { spinner_no: 0 },
+                Call::GetSpinnerPos { spinner_no: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_only_changed_lights() {
+        let service = SdvxioService::new(MockBackend::new(true));
+        let frame = OutputFrame {
+            gpio_lights: Some(0x5),
+            pwm_lights: vec![(0, 10), (1, 20)],
+        };
+        let response = service.request(&Request::WriteOutputFrame(frame), &context());
+        assert_eq!(
+            response,
+            Ok(Response::WriteOutputFrame(MockBackend::WRITE_OUTPUT))
+        );
+        assert_eq!(
+            service.backend.take_calls(),
+            [
+                Call::SetGpioLights { gpio_lights: 0x5 },
+                Call::SetPwmLight {
+                    light_no: 0,
+                    intensity: 10
+                },
+                Call::SetPwmLight {
+                    light_no: 1,
+                    intensity: 20
+                },
+                Call::WriteOutput {},
+            ]
+        );
+
+        // A light set by a plain call counts as applied too
+        service
+            .call(Call::SetPwmLight {
+                light_no: 2,
+                intensity: 30,
+            })
+            .unwrap();
+        service.backend.take_calls();
+        let frame = OutputFrame {
+            gpio_lights: Some(0x5),
+            pwm_lights: vec![(1, 20), (2, 30), (3, 40)],
+        };
+        service
+            .request(&Request::WriteOutputFrame(frame), &context())
+            .unwrap();
+        assert_eq!(
+            service.backend.take_calls(),
+            [
+                Call::SetPwmLight {
+                    light_no: 3,
+                    intensity: 40
+                },
+                Call::WriteOutput {},
+            ]
+        );
+    }
+
+    #[test]
+    fn forgets_applied_lights_once_stopped() {
+        let service = SdvxioService::new(MockBackend::new(true));
+        let frame = OutputFrame {
+            gpio_lights: Some(0x5),
+            pwm_lights: Vec::new(),
+        };
+        service
+            .request(&Request::WriteOutputFrame(frame.clone()), &context())
+            .unwrap();
+        service.stop();
+        service.backend.take_calls();
+        service
+            .request(&Request::WriteOutputFrame(frame), &context())
+            .unwrap();
+        assert_eq!(
+            service.backend.take_calls(),
+            [
+                Call::SetGpioLights { gpio_lights: 0x5 },
+                Call::WriteOutput {}
+            ]
+        );
+    }
+
+    #[test]
+    fn restarts_an_input_stream_that_failed() {
+        struct Closed;
+
+        impl std::io::Write for Closed {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let service = SdvxioService::new(MockBackend::new(true));
+        let context = Context {
+            writer: SharedWriter::new(Box::new(Closed)),
+            shm: None,
+        };
+        let start = Request::StartInputStream { rate_hz: 1000 };
+        assert_eq!(
+            service.request(&start, &context),
+            Ok(Response::StartInputStream(true))
+        );
+        // The first push fails and ends the stream, after which it can be started again
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while service.request(&start, &context).is_err() {
+            assert!(
+                Instant::now() < deadline,
+                "the failed stream was never reaped"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        service.stop();
+    }
+
+    #[test]
+    fn advertises_the_backend_capabilities() {
+        let service = SdvxioService::new(MockBackend::new(true));
+        assert_eq!(service.capabilities(), Capabilities::supported::<Sdvxio>());
+    }
+}
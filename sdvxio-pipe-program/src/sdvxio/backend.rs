@@ -0,0 +1,231 @@
+use super::library;
+use crate::bt5api;
+use sdvxio_pipe_proto::Capabilities;
+use sdvxio_pipe_proto::sdvxio::{Call, InputSnapshot, Return, Sdvxio};
+
+/// An `sdvxio` implementation serving the parent's requests.
+///
+/// Input and output methods may be called concurrently from different threads, but input methods
+/// are never called concurrently with each other, see [`bt5api::lock_input`].
+pub trait SdvxIoBackend: Send + Sync + 'static {
+    fn init(&self) -> bool;
+    fn fini(&self);
+    fn set_gpio_lights(&self, gpio_lights: u32);
+    fn set_pwm_light(&self, light_no: u8, intensity: u8);
+    fn write_output(&self) -> bool;
+    fn read_input(&self) -> bool;
+    fn get_input_gpio_sys(&self) -> u8;
+    fn get_input_gpio(&self, gpio_bank: u8) -> u16;
+    fn get_spinner_pos(&self, spinner_no: u8) -> u16;
+    /// Returns `None` if the backend has no amp volume control.
+    fn set_amp_volume(&self, primary: u8, headphone: u8, subwoofer: u8) -> Option<bool>;
+
+    /// Capabilities of this build the backend supports, advertised to the parent.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::supported::<Sdvxio>()
+    }
+
+    /// Reads input and the state of every input getter.
+    fn read_input_snapshot(&self) -> (bool, InputSnapshot) {
+        let success = self.read_input();
+        let snapshot = InputSnapshot {
+            gpio_sys: self.get_input_gpio_sys(),
+            gpio: [self.get_input_gpio(0), self.get_input_gpio(1)],
+            spinners: [self.get_spinner_pos(0), self.get_spinner_pos(1)],
+        };
+        (success, snapshot)
+    }
+}
+
+/// What a backend method returns, `None` if the backend does not implement the function.
+pub trait Outcome<T> {
+    fn value(self) -> Option<T>;
+}
+
+impl<T> Outcome<T> for T {
+    fn value(self) -> Option<T> {
+        Some(self)
+    }
+}
+
+impl<T> Outcome<T> for Option<T> {
+    fn value(self) -> Option<T> {
+        self
+    }
+}
+
+/// Generates [`call`], serving each function with the backend method of the same name.
+macro_rules! dispatch {
+    ($(
+        $kind:ident {
+            $($variant:ident = $export:ident as $method:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*
+        }
+    )*) => {
+        /// Calls the backend method serving `call`.
+        pub fn call<B: SdvxIoBackend>(backend: &B, call: Call) -> Result<Return, String> {
+            let value = match call {
+                $($(
+                    Call::$variant { $($arg),* } => Return::$variant(
+                        backend.$method($($arg),*).value().ok_or(concat!(
+                            "the backend does not implement ",
+                            stringify!($export)
+                        ))?,
+                    ),
+                )*)*
+            };
+            Ok(value)
+        }
+    };
+}
+
+sdvxio_pipe_proto::sdvxio_api!(dispatch);
+
+/// The wrapped library, once loaded by [`library::load`].
+pub struct Library;
+
+impl SdvxIoBackend for Library {
+    fn init(&self) -> bool {
+        unsafe {
+            library::sdvx_io_init(
+                Some(bt5api::create_thread::<Sdvxio>),
+                Some(bt5api::join_thread),
+                Some(bt5api::destroy_thread),
+            )
+        }
+    }
+
+    fn fini(&self) {
+        unsafe { library::sdvx_io_fini() }
+    }
+
+    fn set_gpio_lights(&self, gpio_lights: u32) {
+        unsafe { library::sdvx_io_set_gpio_lights(gpio_lights) }
+    }
+
+    fn set_pwm_light(&self, light_no: u8, intensity: u8) {
+        unsafe { library::sdvx_io_set_pwm_light(light_no, intensity) }
+    }
+
+    fn write_output(&self) -> bool {
+        unsafe { library::sdvx_io_write_output() }
+    }
+
+    fn read_input(&self) -> bool {
+        unsafe { library::sdvx_io_read_input() }
+    }
+
+    fn get_input_gpio_sys(&self) -> u8 {
+        unsafe { library::sdvx_io_get_input_gpio_sys() }
+    }
+
+    fn get_input_gpio(&self, gpio_bank: u8) -> u16 {
+        unsafe { library::sdvx_io_get_input_gpio(gpio_bank) }
+    }
+
+    fn get_spinner_pos(&self, spinner_no: u8) -> u16 {
+        unsafe { library::sdvx_io_get_spinner_pos(spinner_no) }
+    }
+
+    fn set_amp_volume(&self, primary: u8, headphone: u8, subwoofer: u8) -> Option<bool> {
+        unsafe { library::sdvx_io_set_amp_volume(primary, headphone, subwoofer) }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::supported::<Sdvxio>().difference(library::missing_capabilities())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdvxio::mock::MockBackend;
+
+    #[test]
+    fn calls_reach_the_backend_method() {
+        let cases = [
+            (Call::Init {}, Return::Init(true)),
+            (Call::Fini {}, Return::Fini(())),
+            (Call::ReadInput {}, Return::ReadInput(true)),
+            (
+                Call::GetInputGpioSys {},
+                Return::GetInputGpioSys(MockBackend::GPIO_SYS),
+            ),
+            (
+                Call::GetInputGpio { gpio_bank: 1 },
+                Return::GetInputGpio(MockBackend::gpio(1)),
+            ),
+            (
+                Call::GetSpinnerPos { spinner_no: 1 },
+                Return::GetSpinnerPos(MockBackend::spinner_pos(1)),
+            ),
+            (
+                Call::SetGpioLights {
+                    gpio_lights: 0x8001_2345,
+                },
+                Return::SetGpioLights(()),
+            ),
+            (
+                Call::SetPwmLight {
+                    light_no: 17,
+                    intensity: 200,
+                },
+                Return::SetPwmLight(()),
+            ),
+            (
+                Call::WriteOutput {},
+                Return::WriteOutput(MockBackend::WRITE_OUTPUT),
+            ),
+            (
+                Call::SetAmpVolume {
+                    primary: 0,
+                    headphone: 48,
+                    subwoofer: 96,
+                },
+                Return::SetAmpVolume(true),
+            ),
+            (
+                Call::SetAmpVolume {
+                    primary: 97,
+                    headphone: 1,
+                    subwoofer: 2,
+                },
+                Return::SetAmpVolume(false),
+            ),
+        ];
+
+        let backend = MockBackend::new(true);
+        for (call, value) in cases {
+            assert_eq!(super::call(&backend, call), Ok(value), "{:?}", call);
+            assert_eq!(backend.take_calls(), [call]);
+        }
+    }
+
+    #[test]
+    fn reports_functions_the_backend_lacks() {
+        let backend = MockBackend::new(false);
+        let call = Call::SetAmpVolume {
+            primary: 1,
+            headphone: 2,
+            subwoofer: 3,
+        };
+        let err = super::call(&backend, call).unwrap_err();
+        assert!(err.contains("sdvx_io_set_amp_volume"), "{}", err);
+        assert_eq!(backend.take_calls(), [call]);
+    }
+
+    #[test]
+    fn snapshots_read_input_first() {
+        let backend = MockBackend::new(true);
+        let (success, snapshot) = backend.read_input_snapshot();
+        assert!(success);
+        assert_eq!(
+            snapshot,
+            InputSnapshot {
+                gpio_sys: MockBackend::GPIO_SYS,
+                gpio: [MockBackend::gpio(0), MockBackend::gpio(1)],
+                spinners: [MockBackend::spinner_pos(0), MockBackend::spinner_pos(1)],
+            }
+        );
+        assert_eq!(backend.take_calls()[0], Call::ReadInput {});
+    }
+}
@@ -0,0 +1,31 @@
+use crate::bt5api::{log_formatter_t, thread_create_t, thread_destroy_t, thread_join_t};
+use crate::log;
+use sdvxio_pipe_proto::Capabilities;
+
+crate::bt5api::library! {
+    required {
+        fn sdvx_io_set_loggers(
+            misc: log_formatter_t,
+            info: log_formatter_t,
+            warning: log_formatter_t,
+            fatal: log_formatter_t
+        );
+        fn sdvx_io_init(
+            thread_create: thread_create_t,
+            thread_join: thread_join_t,
+            thread_destroy: thread_destroy_t
+        ) -> bool;
+        fn sdvx_io_fini();
+        fn sdvx_io_set_gpio_lights(gpio_lights: u32);
+        fn sdvx_io_set_pwm_light(light_no: u8, intensity: u8);
+        fn sdvx_io_write_output() -> bool;
+        fn sdvx_io_read_input() -> bool;
+        fn sdvx_io_get_input_gpio_sys() -> u8;
+        fn sdvx_io_get_input_gpio(gpio_bank: u8) -> u16;
+        fn sdvx_io_get_spinner_pos(spinner_no: u8) -> u16;
+    }
+    optional {
+        fn sdvx_io_set_amp_volume(primary: u8, headphone: u8, subwoofer: u8) -> bool
+            => Capabilities::AMP_VOLUME;
+    }
+}
@@ -0,0 +1,103 @@
+//! A backend recording the calls it gets, for tests.
+
+use super::SdvxIoBackend;
+use sdvxio_pipe_proto::sdvxio::{AmpVolume, Call};
+use std::sync::Mutex;
+
+/// Records each method call as the [`Call`] it serves, and returns values telling the methods and
+/// their arguments apart.
+pub struct MockBackend {
+    calls: Mutex<Vec<Call>>,
+    amp_volume: bool,
+}
+
+impl MockBackend {
+    pub const GPIO_SYS: u8 = 0x34;
+    pub const WRITE_OUTPUT: bool = false;
+
+    /// A backend implementing every function, or all but `sdvx_io_set_amp_volume`.
+    pub fn new(amp_volume: bool) -> Self {
+        Self {
+            calls: Mutex::new(Vec::new()),
+            amp_volume,
+        }
+    }
+
+    pub fn gpio(gpio_bank: u8) -> u16 {
+        0x1200 | gpio_bank as u16
+    }
+
+    pub fn spinner_pos(spinner_no: u8) -> u16 {
+        0x200 | spinner_no as u16
+    }
+
+    /// Returns and forgets the calls made so far.
+    pub fn take_calls(&self) -> Vec<Call> {
+        std::mem::take(&mut self.calls.lock().unwrap())
+    }
+
+    fn record(&self, call: Call) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+impl SdvxIoBackend for MockBackend {
+    fn init(&self) -> bool {
+        self.record(Call::Init {});
+        true
+    }
+
+    fn fini(&self) {
+        self.record(Call::Fini {});
+    }
+
+    fn set_gpio_lights(&self, gpio_lights: u32) {
+        self.record(Call::SetGpioLights { gpio_lights });
+    }
+
+    fn set_pwm_light(&self, light_no: u8, intensity: u8) {
+        self.record(Call::SetPwmLight {
+            light_no,
+            intensity,
+        });
+    }
+
+    fn write_output(&self) -> bool {
+        self.record(Call::WriteOutput {});
+        Self::WRITE_OUTPUT
+    }
+
+    fn read_input(&self) -> bool {
+        self.record(Call::ReadInput {});
+        true
+    }
+
+    fn get_input_gpio_sys(&self) -> u8 {
+        self.record(Call::GetInputGpioSys {});
+        Self::GPIO_SYS
+    }
+
+    fn get_input_gpio(&self, gpio_bank: u8) -> u16 {
+        self.record(Call::GetInputGpio { gpio_bank });
+        Self::gpio(gpio_bank)
+    }
+
+    fn get_spinner_pos(&self, spinner_no: u8) -> u16 {
+        self.record(Call::GetSpinnerPos { spinner_no });
+        Self::spinner_pos(spinner_no)
+    }
+
+    fn set_amp_volume(&self, primary: u8, headphone: u8, subwoofer: u8) -> Option<bool> {
+        self.record(Call::SetAmpVolume {
+            primary,
+            headphone,
+            subwoofer,
+        });
+        // Only accepts volumes in range, so that both results can be seen
+        self.amp_volume.then_some(
+            [primary, headphone, subwoofer]
+                .iter()
+                .all(|&volume| AmpVolume::new(volume).is_some()),
+        )
+    }
+}
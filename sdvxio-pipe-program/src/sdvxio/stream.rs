@@ -0,0 +1,125 @@
+use super::SdvxIoBackend;
+use crate::bt5api;
+use crate::connection::Writer;
+use sdvxio_pipe_proto::sdvxio::{Sdvxio, StreamedInput};
+use sdvxio_pipe_proto::{ChildToParent, Message, Sender, ShmRegion};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use thread_priority::{ThreadBuilder, ThreadPriority};
+
+/// Input is pushed at least this often, even if unchanged, so the parent knows the stream is alive.
+const KEEPALIVE: Duration = Duration::from_millis(100);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static THREAD: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+/// Where polled input goes.
+pub enum Sink {
+    /// Pushed to the parent, on change or as a keepalive.
+    Push(Sender<Writer, Message<ChildToParent<Sdvxio>>>),
+    /// Published to the shared input block on every poll.
+    Shm(Arc<ShmRegion>),
+}
+
+/// Starts polling `backend` at `rate_hz` on a dedicated thread, sending input to `sink`.
+pub fn start<B: SdvxIoBackend>(rate_hz: u16, sink: Sink, backend: Arc<B>) -> Result<(), String> {
+    if rate_hz == 0 {
+        return Err("input stream rate must not be 0".to_owned());
+    }
+
+    let mut thread = THREAD.lock().expect("failed to lock input stream");
+    // A stream that ended on an error does not prevent starting a new one
+    if let Some(handle) = thread.take_if(|handle| handle.is_finished()) {
+        let _ = handle.join();
+    }
+    if thread.is_some() {
+        return Err("input stream is already running".to_owned());
+    }
+
+    RUNNING.store(true, Ordering::Release);
+    let period = Duration::from_secs(1) / rate_hz as u32;
+    match ThreadBuilder::default()
+        .name("sdvxio-pipe-input")
+        .priority(ThreadPriority::Max)
+        .spawn_careless(move || poll(period, sink, &*backend))
+    {
+        Ok(handle) => {
+            *thread = Some(handle);
+            log::info!("Streaming input at {} Hz", rate_hz);
+            Ok(())
+        }
+        Err(err) => {
+            RUNNING.store(false, Ordering::Release);
+            Err(format!("cannot start input stream thread: {}", err))
+        }
+    }
+}
+
+pub fn stop() {
+    RUNNING.store(false, Ordering::Release);
+    let handle = THREAD.lock().expect("failed to lock input stream").take();
+    if let Some(handle) = handle {
+        let _ = handle.join();
+        log::info!("Input stream stopped");
+    }
+}
+
+/// Marks the stream as stopped once its thread ends, however it ends.
+struct Running;
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Release);
+    }
+}
+
+fn poll<B: SdvxIoBackend>(period: Duration, mut sink: Sink, backend: &B) {
+    let _running = Running;
+    let mut sequence = 0u32;
+    let mut last = None;
+    let mut last_sent = Instant::now();
+    let mut next = Instant::now();
+
+    while RUNNING.load(Ordering::Acquire) {
+        let current = {
+            let _input = bt5api::lock_input();
+            backend.read_input_snapshot()
+        };
+
+        let (success, snapshot) = current;
+        sequence = sequence.wrapping_add(1);
+        let streamed = StreamedInput {
+            sequence,
+            success,
+            snapshot,
+        };
+        match &mut sink {
+            Sink::Push(tx) => {
+                if last != Some(current) || last_sent.elapsed() >= KEEPALIVE {
+                    if let Err(err) = tx.send(&Message::push(ChildToParent::Push(streamed))) {
+                        log::error!("Failed to push input snapshot: {}", err);
+                        break;
+                    }
+                    last = Some(current);
+                    last_sent = Instant::now();
+                }
+            }
+            Sink::Shm(region) => {
+                if let Err(err) = region.publish_input(&streamed) {
+                    log::error!("Failed to publish input snapshot: {}", err);
+                    break;
+                }
+            }
+        }
+
+        next += period;
+        let now = Instant::now();
+        if next > now {
+            std::thread::sleep(next - now);
+        } else {
+            next = now;
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::connection::Writer;
+use crate::lanes::Lane;
+use sdvxio_pipe_proto::{Api, Capabilities, ShmRegion};
+use std::sync::Arc;
+
+pub type CallOf<S> = <<S as Service>::Api as Api>::Call;
+pub type ReturnOf<S> = <<S as Service>::Api as Api>::Return;
+pub type RequestOf<S> = <<S as Service>::Api as Api>::Request;
+pub type ResponseOf<S> = <<S as Service>::Api as Api>::Response;
+
+/// Serves an API, such as `sdvxio`, on top of the wrapped library.
+///
+/// Input and output functions and requests may be served concurrently from different threads,
+/// but input ones are never served concurrently with each other, see [`bt5api::lock_input`].
+///
+/// [`bt5api::lock_input`]: crate::bt5api::lock_input
+pub trait Service: Send + Sync + 'static {
+    type Api: Api;
+    /// Module definition of the API, listing the functions a library should export.
+    const DEFINITION: &'static str;
+    /// Functions the library may not export.
+    const OPTIONAL: &'static [&'static str];
+
+    /// Capabilities advertised to the parent.
+    fn capabilities(&self) -> Capabilities;
+
+    fn call(&self, call: CallOf<Self>) -> Result<ReturnOf<Self>, String>;
+
+    fn request(
+        &self,
+        request: &RequestOf<Self>,
+        context: &Context,
+    ) -> Result<ResponseOf<Self>, String>;
+
+    /// Lane of a request, or `None` for requests that must run alone.
+    fn lane(request: &RequestOf<Self>) -> Option<Lane>;
+
+    /// Stops what the service runs by itself, before the library is finalized or once the parent
+    /// disconnects.
+    fn stop(&self) {}
+}
+
+/// The connection to the parent, as requests may keep using it.
+pub struct Context {
+    /// Shared with whatever pushes to the parent.
+    pub writer: Writer,
+    pub shm: Option<Arc<ShmRegion>>,
+}
@@ -0,0 +1,145 @@
+use crate::log;
+use std::fs::File;
+use std::io::{BufRead, BufReader, PipeReader};
+
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Moves the protocol off the process's stdout, so that the wrapped library cannot corrupt it.
+///
+/// Returns a private duplicate of the original stdout. Stdout and stderr are then pointed at
+/// pipes whose lines are logged with a `stdout` or `stderr` target, by threads named after `api`.
+pub fn protect(api: &str) -> std::io::Result<File> {
+    let protocol = sys::duplicate_stdout()?;
+    capture(api, Stream::Stdout)?;
+    capture(api, Stream::Stderr)?;
+    Ok(protocol)
+}
+
+fn capture(api: &str, stream: Stream) -> std::io::Result<()> {
+    let (reader, writer) = std::io::pipe()?;
+    sys::redirect(stream, writer)?;
+    std::thread::Builder::new()
+        .name(format!("{}-pipe-{}", api, stream.target()))
+        .spawn(move || forward(reader, stream))?;
+    Ok(())
+}
+
+fn forward(reader: PipeReader, stream: Stream) {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            continue;
+        }
+        match stream {
+            Stream::Stdout => log::info!(target: stream.target(), "{}", line),
+            Stream::Stderr => log::warn!(target: stream.target(), "{}", line),
+        }
+    }
+}
+
+impl Stream {
+    fn target(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use super::Stream;
+    use std::fs::File;
+    use std::io::PipeWriter;
+    use std::os::fd::{AsFd, AsRawFd};
+    use std::os::raw::c_int;
+
+    unsafe extern "C" {
+        fn dup2(old: c_int, new: c_int) -> c_int;
+    }
+
+    pub fn duplicate_stdout() -> std::io::Result<File> {
+        Ok(File::from(std::io::stdout().as_fd().try_clone_to_owned()?))
+    }
+
+    pub fn redirect(stream: Stream, writer: PipeWriter) -> std::io::Result<()> {
+        let fd = match stream {
+            Stream::Stdout => 1,
+            Stream::Stderr => 2,
+        };
+        if unsafe { dup2(writer.as_raw_fd(), fd) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::Stream;
+    use std::fs::File;
+    use std::io::PipeWriter;
+    use std::os::raw::{c_int, c_void};
+    use std::os::windows::io::{AsHandle, IntoRawHandle, OwnedHandle};
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+
+    unsafe extern "system" {
+        fn SetStdHandle(std_handle: u32, handle: *mut c_void) -> i32;
+    }
+
+    // Only the C runtime this program is linked against is redirected, a library linked against
+    // another one keeps writing to the original handle.
+    unsafe extern "C" {
+        fn _open_osfhandle(handle: isize, flags: c_int) -> c_int;
+        fn _dup2(old: c_int, new: c_int) -> c_int;
+        fn _close(fd: c_int) -> c_int;
+    }
+
+    pub fn duplicate_stdout() -> std::io::Result<File> {
+        Ok(File::from(
+            std::io::stdout().as_handle().try_clone_to_owned()?,
+        ))
+    }
+
+    pub fn redirect(stream: Stream, writer: PipeWriter) -> std::io::Result<()> {
+        let (std_handle, fd) = match stream {
+            Stream::Stdout => (STD_OUTPUT_HANDLE, 1),
+            Stream::Stderr => (STD_ERROR_HANDLE, 2),
+        };
+
+        // The C runtime takes ownership of the handle it is given
+        let crt_handle: OwnedHandle = writer.as_handle().try_clone_to_owned()?;
+        let crt_fd = unsafe { _open_osfhandle(crt_handle.into_raw_handle() as isize, 0) };
+        if crt_fd < 0 {
+            return Err(std::io::Error::other("cannot open a C runtime descriptor"));
+        }
+        let duplicated = unsafe { _dup2(crt_fd, fd) };
+        unsafe { _close(crt_fd) };
+        if duplicated < 0 {
+            return Err(std::io::Error::other(
+                "cannot redirect the C runtime descriptor",
+            ));
+        }
+
+        // The standard handle keeps the pipe open for the rest of the process
+        if unsafe { SetStdHandle(std_handle, writer.into_raw_handle()) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! Loads the `sdvxio-pipe` proxy like a game would, with this program wrapping `sdvxio-dummy`.
+
+use libloading::{Library, Symbol};
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX, EXE_SUFFIX};
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+type Init = unsafe extern "C" fn(*const c_void, *const c_void, *const c_void) -> bool;
+type Fini = unsafe extern "C" fn();
+type ReadInput = unsafe extern "C" fn() -> bool;
+type GetInputGpioSys = unsafe extern "C" fn() -> u8;
+type GetU16 = unsafe extern "C" fn(u8) -> u16;
+type SetGpioLights = unsafe extern "C" fn(u32);
+type SetPwmLight = unsafe extern "C" fn(u8, u8);
+type WriteOutput = unsafe extern "C" fn() -> bool;
+type SetAmpVolume = unsafe extern "C" fn(u8, u8, u8) -> bool;
+
+fn library_name(name: &str) -> String {
+    format!("{}{}{}", DLL_PREFIX, name, DLL_SUFFIX)
+}
+
+/// Builds the proxy and the dummy library, which are not dependencies of this package.
+fn build_libraries(target_dir: &Path) -> PathBuf {
+    let status = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned()))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args([
+            "build",
+            "-p",
+            "sdvxio-pipe",
+            "-p",
+            "sdvxio-dummy",
+            "--target-dir",
+        ])
+        .arg(target_dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "cannot build the libraries");
+    target_dir.join("debug")
+}
+
+/// Lays out a game directory with the proxy, and the program and dummy library in `pipe`.
+fn install(game_dir: &Path, libraries: &Path) {
+    let pipe_dir = game_dir.join("pipe");
+    if game_dir.exists() {
+        std::fs::remove_dir_all(game_dir).unwrap();
+    }
+    std::fs::create_dir_all(&pipe_dir).unwrap();
+    let copy = |from: PathBuf, to: PathBuf| {
+        std::fs::copy(&from, &to).unwrap_or_else(|err| panic!("{}: {}", from.display(), err));
+    };
+    copy(
+        libraries.join(library_name("sdvxio")),
+        game_dir.join(library_name("sdvxio")),
+    );
+    copy(
+        libraries.join(library_name("sdvxio_dummy")),
+        pipe_dir.join(library_name("sdvxio_dummy")),
+    );
+    copy(
+        PathBuf::from(env!("CARGO_BIN_EXE_sdvxio-pipe-program")),
+        pipe_dir.join(format!("sdvxio-pipe-program{}", EXE_SUFFIX)),
+    );
+    std::fs::write(
+        game_dir.join("sdvxio-pipe.toml"),
+        format!(
+            "[child]\nlibrary = \"pipe/{}\"\n",
+            library_name("sdvxio_dummy")
+        ),
+    )
+    .unwrap();
+}
+
+/// Waits for the program to log `line`, as it writes its log on its own.
+fn assert_logged(log: &Path, line: &str) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let contents = std::fs::read_to_string(log).unwrap_or_default();
+        if contents.contains(line) {
+            return;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "{:?} not logged in:\n{}",
+            line,
+            contents
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn wraps_the_dummy_library() {
+    let tmp = Path::new(env!("CARGO_TARGET_TMPDIR")).join("end-to-end");
+    let libraries = build_libraries(&tmp.join("target"));
+    let game_dir = tmp.join("game");
+    install(&game_dir, &libraries);
+
+    unsafe {
+        let proxy = Library::new(game_dir.join(library_name("sdvxio"))).unwrap();
+        let init: Symbol<Init> = proxy.get(b"sdvx_io_init").unwrap();
+        let fini: Symbol<Fini> = proxy.get(b"sdvx_io_fini").unwrap();
+        let read_input: Symbol<ReadInput> = proxy.get(b"sdvx_io_read_input").unwrap();
+        let get_input_gpio_sys: Symbol<GetInputGpioSys> =
+            proxy.get(b"sdvx_io_get_input_gpio_sys").unwrap();
+        let get_input_gpio: Symbol<GetU16> = proxy.get(b"sdvx_io_get_input_gpio").unwrap();
+        let get_spinner_pos: Symbol<GetU16> = proxy.get(b"sdvx_io_get_spinner_pos").unwrap();
+        let set_gpio_lights: Symbol<SetGpioLights> = proxy.get(b"sdvx_io_set_gpio_lights").unwrap();
+        let set_pwm_light: Symbol<SetPwmLight> = proxy.get(b"sdvx_io_set_pwm_light").unwrap();
+        let write_output: Symbol<WriteOutput> = proxy.get(b"sdvx_io_write_output").unwrap();
+        let set_amp_volume: Symbol<SetAmpVolume> = proxy.get(b"sdvx_io_set_amp_volume").unwrap();
+
+        let null = std::ptr::null();
+        assert!(init(null, null, null));
+
+        // Each read advances the dummy by a frame, turning the knobs one step each way
+        for frame in 1..=3 {
+            assert!(read_input());
+            assert_eq!(get_input_gpio_sys(), 0);
+            assert_eq!([get_input_gpio(0), get_input_gpio(1)], [0, 0]);
+            assert_eq!(get_spinner_pos(0), frame);
+            assert_eq!(get_spinner_pos(1), 1024 - frame);
+        }
+
+        set_gpio_lights(1 << 12);
+        set_pwm_light(3, 200);
+        assert!(write_output());
+        assert!(set_amp_volume(10, 20, 30));
+        assert!(!set_amp_volume(97, 0, 0));
+
+        fini();
+    }
+
+    let log = game_dir.join("pipe").join("sdvxio-pipe.log");
+    assert_logged(&log, "Initialized");
+    // Printed by the library to stdout, where it would have corrupted the protocol
+    assert_logged(&log, "sdvxio-dummy printed to stdout");
+    assert_logged(&log, "GPIO lights GpioLights(START)");
+    assert_logged(&log, "PWM light 3 set to 200");
+    assert_logged(
+        &log,
+        "Amp volume set to 10 primary, 20 headphone, 30 subwoofer",
+    );
+    assert_logged(&log, "Rejected amp volume 97 primary");
+    assert_logged(&log, "Finalized");
+}
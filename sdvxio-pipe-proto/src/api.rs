@@ -0,0 +1,110 @@
+use crate::Capabilities;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::hash::Hash;
+
+/// A BT5 IO API bridged to the child, such as [`Sdvxio`](crate::sdvxio::Sdvxio).
+///
+/// Its functions are listed in a table expanded by `define_calls!` into [`Api::Call`] and
+/// [`Api::Return`]. Requests, responses and pushes carry what the API exchanges beyond them.
+pub trait Api: fmt::Debug + Clone + Send + Sync + 'static {
+    /// Name of the API, which is also the name of the libraries implementing it.
+    const NAME: &'static str;
+    /// Capabilities this build implements for the API, besides the generic ones.
+    const CAPABILITIES: Capabilities;
+    /// Initializes the library, its value telling whether it succeeded, see [`Api::initialized`].
+    const INIT: Self::Call;
+    /// Shuts the library down, after which the child exits.
+    const FINI: Self::Call;
+
+    type Call: ApiCall;
+    type Return: Payload;
+    /// Requests the child answers beyond the functions of the API, such as reading all input at
+    /// once.
+    type Request: Payload;
+    type Response: Payload;
+    /// Sent unprompted by the child, such as streamed input.
+    type Push: Payload;
+
+    /// Whether `value`, returned by [`Api::INIT`], tells the library initialized.
+    fn initialized(value: &Self::Return) -> bool;
+}
+
+/// Bounds of everything the parent and the child exchange.
+pub trait Payload:
+    fmt::Debug + Clone + PartialEq + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+}
+
+impl<T> Payload for T where
+    T: fmt::Debug + Clone + PartialEq + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+}
+
+/// A function of an API and its arguments.
+pub trait ApiCall: Payload + Copy + Eq + Hash {
+    /// Name of the function as exported by the libraries implementing the API.
+    fn name(&self) -> &'static str;
+    fn kind(&self) -> CallKind;
+}
+
+/// Thread a function is called from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    /// Called once, alone.
+    Lifecycle,
+    Input,
+    Output,
+}
+
+/// Requests, responses or pushes of an API that has none.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Never {}
+
+/// Defines the `Call` and `Return` enums of a function table, such as the one of
+/// [`sdvxio_api`](crate::sdvxio_api).
+///
+/// Each function is given as `Variant = export as method(args) -> ret;`, where `Variant` names
+/// its `Call` and `Return` variants and `method` the backend method serving it. Functions are
+/// grouped by the thread the game calls them from.
+macro_rules! define_calls {
+    ($(
+        $kind:ident {
+            $($variant:ident = $export:ident as $method:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*
+        }
+    )*) => {
+        /// A forwarded function and its arguments.
+        #[derive(
+            Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+        )]
+        pub enum Call {
+            $($($variant { $($arg: $ty),* },)*)*
+        }
+
+        /// Value returned by a forwarded function, in the variant of its [`Call`].
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+        pub enum Return {
+            $($($variant($ret),)*)*
+        }
+
+        impl $crate::ApiCall for Call {
+            fn name(&self) -> &'static str {
+                match self {
+                    $($(Call::$variant { .. } => stringify!($export),)*)*
+                }
+            }
+
+            fn kind(&self) -> $crate::CallKind {
+                match self {
+                    $($(Call::$variant { .. } => define_calls!(@kind $kind),)*)*
+                }
+            }
+        }
+    };
+    (@kind lifecycle) => { $crate::CallKind::Lifecycle };
+    (@kind input) => { $crate::CallKind::Input };
+    (@kind output) => { $crate::CallKind::Output };
+}
+
+pub(crate) use define_calls;
@@ -0,0 +1,225 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::io::{Read, Write};
+
+const NONCE_SIZE: usize = 32;
+const TAG_SIZE: usize = 32;
+
+/// Secret shared by the parent and the program, proven by both sides before any message is
+/// exchanged over a socket.
+#[derive(Clone)]
+pub struct PreSharedKey(Vec<u8>);
+
+impl PreSharedKey {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self(secret.into())
+    }
+
+    /// Proves the parent knows the key and checks that the program does too.
+    ///
+    /// The parent sends a nonce, the program answers with its own nonce and a tag over both, and
+    /// the parent answers with a tag over both in the other order.
+    pub fn authenticate_parent<S: Read + Write>(&self, stream: &mut S) -> std::io::Result<()> {
+        let parent_nonce = nonce()?;
+        stream.write_all(&parent_nonce)?;
+        stream.flush()?;
+
+        let mut child_nonce = [0u8; NONCE_SIZE];
+        let mut child_tag = [0u8; TAG_SIZE];
+        stream.read_exact(&mut child_nonce)?;
+        stream.read_exact(&mut child_tag)?;
+        self.verify(b"child", &parent_nonce, &child_nonce, &child_tag)?;
+
+        stream.write_all(&self.tag(b"parent", &child_nonce, &parent_nonce))?;
+        stream.flush()
+    }
+
+    /// Counterpart of [`PreSharedKey::authenticate_parent`], run by the program.
+    pub fn authenticate_child<S: Read + Write>(&self, stream: &mut S) -> std::io::Result<()> {
+        let mut parent_nonce = [0u8; NONCE_SIZE];
+        stream.read_exact(&mut parent_nonce)?;
+
+        let child_nonce = nonce()?;
+        stream.write_all(&child_nonce)?;
+        stream.write_all(&self.tag(b"child", &parent_nonce, &child_nonce))?;
+        stream.flush()?;
+
+        let mut parent_tag = [0u8; TAG_SIZE];
+        stream.read_exact(&mut parent_tag)?;
+        self.verify(b"parent", &child_nonce, &parent_nonce, &parent_tag)
+    }
+
+    fn mac(&self, role: &[u8], first: &[u8], second: &[u8]) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any size");
+        mac.update(role);
+        mac.update(first);
+        mac.update(second);
+        mac
+    }
+
+    fn tag(&self, role: &[u8], first: &[u8], second: &[u8]) -> [u8; TAG_SIZE] {
+        self.mac(role, first, second).finalize().into_bytes().into()
+    }
+
+    fn verify(&self, role: &[u8], first: &[u8], second: &[u8], tag: &[u8]) -> std::io::Result<()> {
+        self.mac(role, first, second)
+            .verify_slice(tag)
+            .map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "authentication failed, the pre-shared keys differ",
+                )
+            })
+    }
+}
+
+impl std::fmt::Debug for PreSharedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PreSharedKey(..)")
+    }
+}
+
+fn nonce() -> std::io::Result<[u8; NONCE_SIZE]> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    getrandom::fill(&mut nonce)
+        .map_err(|err| std::io::Error::other(format!("cannot generate a nonce: {}", err)))?;
+    Ok(nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, ErrorKind};
+    use std::net::{TcpListener, TcpStream};
+
+    /// Answers with prepared bytes and records what is written.
+    struct Script {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Script {
+        fn new(input: Vec<u8>) -> Self {
+            Self {
+                input: Cursor::new(input),
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs both sides over a loopback connection, returning the parent's and the child's results.
+    fn authenticate(
+        parent: &PreSharedKey,
+        child: &PreSharedKey,
+    ) -> (std::io::Result<()>, std::io::Result<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut parent_stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut child_stream, _) = listener.accept().unwrap();
+        let child = child.clone();
+        let child = std::thread::spawn(move || {
+            let result = child.authenticate_child(&mut child_stream);
+            // Unblocks the parent if the child gave up early
+            drop(child_stream);
+            result
+        });
+        let parent = parent.authenticate_parent(&mut parent_stream);
+        drop(parent_stream);
+        (parent, child.join().unwrap())
+    }
+
+    #[test]
+    fn accepts_the_same_key() {
+        let key = PreSharedKey::new("secret");
+        let (parent, child) = authenticate(&key, &key);
+        parent.unwrap();
+        child.unwrap();
+    }
+
+    #[test]
+    fn rejects_a_different_key() {
+        let (parent, child) =
+            authenticate(&PreSharedKey::new("secret"), &PreSharedKey::new("guess"));
+        assert_eq!(parent.unwrap_err().kind(), ErrorKind::PermissionDenied);
+        // The parent hangs up rather than proving itself to a program it does not trust
+        assert_eq!(child.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_a_replayed_child_answer() {
+        let key = PreSharedKey::new("secret");
+        let mut recorded = Script::new([1u8; NONCE_SIZE].to_vec());
+        key.authenticate_child(&mut recorded).unwrap_err();
+
+        // A new parent challenges with a fresh nonce, which the recorded tag does not cover
+        let mut replay = Script::new(recorded.output);
+        let err = key.authenticate_parent(&mut replay).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(replay.output.len(), NONCE_SIZE);
+    }
+
+    #[test]
+    fn rejects_a_replayed_parent_answer() {
+        let key = PreSharedKey::new("secret");
+        let parent_nonce = [1u8; NONCE_SIZE];
+        let child_nonce = [2u8; NONCE_SIZE];
+        let mut replay = parent_nonce.to_vec();
+        replay.extend(key.tag(b"parent", &child_nonce, &parent_nonce));
+
+        // The program picks a fresh nonce, so the tag over the recorded one does not match
+        let err = key
+            .authenticate_child(&mut Script::new(replay))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn rejects_a_reflected_tag() {
+        let key = PreSharedKey::new("secret");
+        let mut recorded = Script::new([1u8; NONCE_SIZE].to_vec());
+        key.authenticate_child(&mut recorded).unwrap_err();
+        let child_tag = &recorded.output[NONCE_SIZE..];
+
+        // Sending the program's own tag back fails, as each side signs its role
+        let mut reflected = [1u8; NONCE_SIZE].to_vec();
+        reflected.extend(child_tag);
+        let err = key
+            .authenticate_child(&mut Script::new(reflected))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn rejects_truncated_answers() {
+        let key = PreSharedKey::new("secret");
+        let mut child_answer = [2u8; NONCE_SIZE].to_vec();
+        child_answer.extend(&[0u8; TAG_SIZE][..TAG_SIZE - 1]);
+        let mut script = Script::new(child_answer);
+        let err = key.authenticate_parent(&mut script).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(script.output.len(), NONCE_SIZE);
+
+        let mut parent_answer = [1u8; NONCE_SIZE].to_vec();
+        parent_answer.extend(&[0u8; TAG_SIZE][..TAG_SIZE / 2]);
+        let err = key
+            .authenticate_child(&mut Script::new(parent_answer))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
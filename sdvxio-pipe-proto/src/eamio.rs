@@ -0,0 +1,75 @@
+//! The `eamio` API of e-amusement card readers.
+
+use crate::api::define_calls;
+use crate::{Api, Capabilities, Never};
+use serde::{Deserialize, Serialize};
+
+/// Bytes of a card ID.
+pub const CARD_ID_SIZE: usize = 8;
+
+/// Invokes `$callback!` with the `eamio.h` functions forwarded to the child, see
+/// [`sdvxio_api`](crate::sdvxio_api).
+///
+/// Every function is an input one, even `eam_io_card_slot_cmd`: nothing in `eamio.h` allows a
+/// reader to be driven from several threads, so the child serializes them all, as the game's
+/// single IO thread would.
+///
+/// `eam_io_read_card` fills a buffer given by the game, so it is forwarded as
+/// [`Request::ReadCard`] rather than listed here. `eam_io_get_config_api` is not forwarded.
+#[macro_export]
+macro_rules! eamio_api {
+    ($callback:ident $(, $($args:tt)*)?) => {
+        $callback! {
+            $($($args)*,)?
+            lifecycle {
+                Init = eam_io_init as init() -> bool;
+                Fini = eam_io_fini as fini() -> ();
+            }
+            input {
+                GetKeypadState = eam_io_get_keypad_state as get_keypad_state(unit_no: u8) -> u16;
+                GetSensorState = eam_io_get_sensor_state as get_sensor_state(unit_no: u8) -> u8;
+                Poll = eam_io_poll as poll(unit_no: u8) -> bool;
+                CardSlotCmd = eam_io_card_slot_cmd as card_slot_cmd(unit_no: u8, cmd: u8) -> bool;
+            }
+        }
+    };
+}
+
+crate::eamio_api!(define_calls);
+
+/// The `eamio` API.
+#[derive(Debug, Clone, Copy)]
+pub struct Eamio;
+
+impl Api for Eamio {
+    const NAME: &'static str = "eamio";
+    const CAPABILITIES: Capabilities = Capabilities::empty();
+    const INIT: Call = Call::Init {};
+    const FINI: Call = Call::Fini {};
+
+    type Call = Call;
+    type Return = Return;
+    type Request = Request;
+    type Response = Response;
+    type Push = Never;
+
+    fn initialized(value: &Return) -> bool {
+        *value == Return::Init(true)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Request {
+    /// Calls `eam_io_read_card` with a buffer of [`CARD_ID_SIZE`] bytes.
+    ReadCard { unit_no: u8 },
+}
+
+/// Answers the [`Request`] of the same name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Response {
+    /// The card type returned by `eam_io_read_card`, and the ID it wrote.
+    ReadCard {
+        card_type: u8,
+        card_id: [u8; CARD_ID_SIZE],
+    },
+}
@@ -0,0 +1,252 @@
+use crate::Arch;
+use crate::pe::invalid;
+use std::path::Path;
+
+pub(crate) const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+const MACHINE_386: u16 = 3;
+const MACHINE_X86_64: u16 = 62;
+const MACHINE_AARCH64: u16 = 183;
+
+const SECTION_DYNAMIC: u32 = 6;
+const SECTION_DYNSYM: u32 = 11;
+const TAG_NEEDED: u64 = 1;
+const UNDEFINED_SECTION: u16 = 0;
+const BINDING_GLOBAL: u8 = 1;
+const BINDING_WEAK: u8 = 2;
+
+/// The parts of an ELF shared object needed to check a library without loading it.
+#[derive(Debug, Clone)]
+pub struct ElfImage {
+    pub machine: u16,
+    /// Names of the symbols the image defines for others.
+    pub exports: Vec<String>,
+    /// Names of the libraries the image needs, from its `DT_NEEDED` entries.
+    pub imports: Vec<String>,
+}
+
+/// Reads the fields of a 32 or 64-bit ELF image of either endianness.
+struct Reader<'a> {
+    data: &'a [u8],
+    wide: bool,
+    big_endian: bool,
+}
+
+struct Section {
+    kind: u32,
+    offset: usize,
+    size: usize,
+    link: usize,
+    entry_size: usize,
+}
+
+impl ElfImage {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> std::io::Result<Self> {
+        if !data.starts_with(ELF_MAGIC) {
+            return Err(invalid("missing ELF header"));
+        }
+        let reader = Reader::new(data)?;
+        let machine = reader.u16(18)?;
+
+        let (table, entry_size, count) = if reader.wide {
+            (reader.offset(0x28)?, reader.u16(0x3a)?, reader.u16(0x3c)?)
+        } else {
+            (reader.offset(0x20)?, reader.u16(0x2e)?, reader.u16(0x30)?)
+        };
+        let sections = (0..count as usize)
+            .map(|index| reader.section(table.saturating_add(index * entry_size as usize)))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let strings = |section: &Section| {
+            sections
+                .get(section.link)
+                .ok_or_else(|| invalid(format!("no string table at section {}", section.link)))
+        };
+
+        let mut exports = Vec::new();
+        let mut imports = Vec::new();
+        for section in &sections {
+            let entries = (0..section.size / section.entry_size.max(1))
+                .map(|index| section.offset.saturating_add(index * section.entry_size));
+            match section.kind {
+                SECTION_DYNSYM => {
+                    let strings = strings(section)?;
+                    for entry in entries {
+                        let (name, info, index) = if reader.wide {
+                            (
+                                reader.u32(entry)?,
+                                reader.u8(entry + 4)?,
+                                reader.u16(entry + 6)?,
+                            )
+                        } else {
+                            (
+                                reader.u32(entry)?,
+                                reader.u8(entry + 12)?,
+                                reader.u16(entry + 14)?,
+                            )
+                        };
+                        let binding = info >> 4;
+                        if name != 0
+                            && index != UNDEFINED_SECTION
+                            && (binding == BINDING_GLOBAL || binding == BINDING_WEAK)
+                        {
+                            exports.push(reader.string(strings, name as usize)?);
+                        }
+                    }
+                }
+                SECTION_DYNAMIC => {
+                    let strings = strings(section)?;
+                    for entry in entries {
+                        if reader.word(entry)? == TAG_NEEDED {
+                            let name = reader.offset(entry + section.entry_size / 2)?;
+                            imports.push(reader.string(strings, name)?);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            machine,
+            exports,
+            imports,
+        })
+    }
+
+    /// Architecture the image was built for.
+    pub fn arch(&self) -> Arch {
+        machine_arch(self.machine)
+    }
+}
+
+pub(crate) fn machine_arch(machine: u16) -> Arch {
+    match machine {
+        MACHINE_386 => Arch::X86,
+        MACHINE_X86_64 => Arch::X86_64,
+        MACHINE_AARCH64 => Arch::Aarch64,
+        _ => Arch::Unknown,
+    }
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> std::io::Result<Self> {
+        let (Some(&class), Some(&encoding)) = (data.get(4), data.get(5)) else {
+            return Err(invalid("truncated image"));
+        };
+        let wide = match class {
+            1 => false,
+            2 => true,
+            _ => return Err(invalid(format!("unknown ELF class {}", class))),
+        };
+        let big_endian = match encoding {
+            1 => false,
+            2 => true,
+            _ => return Err(invalid(format!("unknown ELF data encoding {}", encoding))),
+        };
+        Ok(Self {
+            data,
+            wide,
+            big_endian,
+        })
+    }
+
+    fn section(&self, header: usize) -> std::io::Result<Section> {
+        Ok(if self.wide {
+            Section {
+                kind: self.u32(header + 4)?,
+                offset: self.offset(header + 24)?,
+                size: self.offset(header + 32)?,
+                link: self.u32(header + 40)? as usize,
+                entry_size: self.offset(header + 56)?,
+            }
+        } else {
+            Section {
+                kind: self.u32(header + 4)?,
+                offset: self.offset(header + 16)?,
+                size: self.offset(header + 20)?,
+                link: self.u32(header + 24)? as usize,
+                entry_size: self.offset(header + 36)?,
+            }
+        })
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> std::io::Result<[u8; N]> {
+        // Offsets come from the image, so they may point anywhere
+        let mut bytes: [u8; N] = self
+            .data
+            .get(offset..offset.saturating_add(N))
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| invalid("truncated image"))?;
+        if self.big_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    fn u8(&self, offset: usize) -> std::io::Result<u8> {
+        self.bytes(offset).map(u8::from_le_bytes)
+    }
+
+    fn u16(&self, offset: usize) -> std::io::Result<u16> {
+        self.bytes(offset).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, offset: usize) -> std::io::Result<u32> {
+        self.bytes(offset).map(u32::from_le_bytes)
+    }
+
+    /// A field whose width depends on the class of the image.
+    fn word(&self, offset: usize) -> std::io::Result<u64> {
+        if self.wide {
+            self.bytes(offset).map(u64::from_le_bytes)
+        } else {
+            self.u32(offset).map(u64::from)
+        }
+    }
+
+    /// An offset or size within the image.
+    fn offset(&self, offset: usize) -> std::io::Result<usize> {
+        let word = self.word(offset)?;
+        usize::try_from(word).map_err(|_| invalid(format!("offset {:#x} is out of range", word)))
+    }
+
+    fn string(&self, table: &Section, offset: usize) -> std::io::Result<String> {
+        let bytes = self
+            .data
+            .get(table.offset.saturating_add(offset)..)
+            .ok_or_else(|| invalid("truncated image"))?;
+        let end = bytes
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| invalid("unterminated name"))?;
+        Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_running_executable() {
+        let image = ElfImage::read(&std::env::current_exe().unwrap()).unwrap();
+        assert_eq!(image.arch(), Arch::current());
+        assert!(
+            image
+                .imports
+                .iter()
+                .any(|import| import.starts_with("libc.so"))
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let data = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        assert!(ElfImage::parse(&data[..0x30]).is_err());
+        assert!(ElfImage::parse(b"MZ").is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use crate::Api;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Version of the wire protocol, bumped whenever `ParentToChild` or `ChildToParent` change.
+pub const PROTOCOL_VERSION: u16 = 9;
+
+/// Exchanged by both sides before any other message.
+///
+/// The layout of this struct, the position of the `Hello` and `HelloResponse` variants and the
+/// framing of [`Sender`](crate::Sender) must stay the same, so that builds speaking different
+/// protocol versions can still tell each other apart.
+///
+/// This only holds between builds that share the current framing. Older builds cannot decode
+/// the `Hello` at all, and those predating the handshake read it as their first request, so the
+/// parent and the program should always come from the same release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub protocol_version: u16,
+    pub version: Version,
+    pub arch: Arch,
+    pub capabilities: Capabilities,
+}
+
+impl Hello {
+    pub fn current<A: Api>() -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            version: Version::current(),
+            arch: Arch::current(),
+            capabilities: Capabilities::supported::<A>(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl Version {
+    pub fn current() -> Self {
+        let parse = |value: &str| value.parse().unwrap_or_default();
+        Self {
+            major: parse(env!("CARGO_PKG_VERSION_MAJOR")),
+            minor: parse(env!("CARGO_PKG_VERSION_MINOR")),
+            patch: parse(env!("CARGO_PKG_VERSION_PATCH")),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Arch {
+    X86,
+    X86_64,
+    Aarch64,
+    Unknown,
+}
+
+impl Arch {
+    pub const fn current() -> Self {
+        if cfg!(target_arch = "x86") {
+            Arch::X86
+        } else if cfg!(target_arch = "x86_64") {
+            Arch::X86_64
+        } else if cfg!(target_arch = "aarch64") {
+            Arch::Aarch64
+        } else {
+            Arch::Unknown
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Arch::X86 => "x86",
+            Arch::X86_64 => "x64",
+            Arch::Aarch64 => "arm64",
+            Arch::Unknown => "unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Optional protocol features a side supports. Only the features present on both sides are used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// The child answers `sdvxio::Request::ReadInputSnapshot`.
+    pub const INPUT_SNAPSHOT: Self = Self(1 << 0);
+    /// The child answers `sdvxio::Request::WriteOutputFrame`.
+    pub const OUTPUT_FRAME: Self = Self(1 << 1);
+    /// The child pushes streamed input after a `sdvxio::Request::StartInputStream`.
+    pub const INPUT_STREAM: Self = Self(1 << 2);
+    /// The child forwards its logs as `Log` messages after a `SetLogLevel` request.
+    pub const LOG_FORWARD: Self = Self(1 << 3);
+    /// The wrapped library exports `sdvx_io_set_amp_volume`, so `sdvxio::Call::SetAmpVolume` has
+    /// an effect.
+    pub const AMP_VOLUME: Self = Self(1 << 4);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// Capabilities implemented by this build for `A`.
+    pub fn supported<A: Api>() -> Self {
+        Self::LOG_FORWARD | A::CAPABILITIES
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#010x}", self.0)
+    }
+}
@@ -0,0 +1,68 @@
+use crate::elf::{ELF_MAGIC, machine_arch};
+use crate::pe::invalid;
+use crate::{Arch, ElfImage, PeImage};
+use std::path::Path;
+
+/// Executable format of a library, telling how its imports are looked up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Pe,
+    Elf,
+}
+
+/// The parts of a PE or ELF library needed to check it without loading it.
+#[derive(Debug, Clone)]
+pub struct LibraryImage {
+    pub format: ImageFormat,
+    pub arch: Arch,
+    /// Names of the exported functions.
+    pub exports: Vec<String>,
+    /// Names of the libraries loaded along with the image.
+    pub imports: Vec<String>,
+}
+
+impl LibraryImage {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let data = std::fs::read(path)?;
+        if data.starts_with(ELF_MAGIC) {
+            let image = ElfImage::parse(&data)?;
+            return Ok(Self {
+                format: ImageFormat::Elf,
+                arch: image.arch(),
+                exports: image.exports,
+                imports: image.imports,
+            });
+        }
+        if !data.starts_with(b"MZ") {
+            return Err(invalid("neither a PE nor an ELF image"));
+        }
+        let image = PeImage::parse(&data)?;
+        Ok(Self {
+            format: ImageFormat::Pe,
+            arch: image.arch(),
+            exports: image.exports,
+            imports: image.imports,
+        })
+    }
+}
+
+/// Architecture of the PE or ELF image at `path`, read from its headers.
+pub fn image_arch(path: &Path) -> std::io::Result<Arch> {
+    let data = std::fs::read(path)?;
+    if data.starts_with(ELF_MAGIC) {
+        return elf_arch(&data);
+    }
+    PeImage::parse(&data).map(|image| image.arch())
+}
+
+fn elf_arch(data: &[u8]) -> std::io::Result<Arch> {
+    let (Some(&encoding), Some(&[low, high])) = (data.get(5), data.get(18..20)) else {
+        return Err(invalid("truncated image"));
+    };
+    let machine = match encoding {
+        1 => u16::from_le_bytes([low, high]),
+        2 => u16::from_be_bytes([low, high]),
+        _ => return Err(invalid(format!("unknown ELF data encoding {}", encoding))),
+    };
+    Ok(machine_arch(machine))
+}
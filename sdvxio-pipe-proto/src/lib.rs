@@ -1,5 +1,27 @@
+mod api;
+mod auth;
+mod elf;
+mod handshake;
+mod image;
+mod logging;
+mod pe;
 mod pipe;
+mod shm;
+mod socket;
+
+pub mod eamio;
+pub mod sdvxio;
+
+pub use api::*;
+pub use auth::*;
+pub use elf::*;
+pub use handshake::*;
+pub use image::*;
+pub use logging::*;
+pub use pe::*;
 pub use pipe::*;
+pub use shm::*;
+pub use socket::*;
 
 use serde::{Deserialize, Serialize};
 
@@ -9,41 +31,66 @@ pub struct Message<T> {
     pub payload: T,
 }
 
-#[derive(Serialize, Deserialize)]
-pub enum ChildToParent {
-    InitResponse(bool),
-    WriteOutputResponse(bool),
-    ReadInputResponse(bool),
-    GetInputGpioSysResponse(u8),
-    GetInputGpioResponse(u16),
-    GetSpinnerPosResponse(u16),
-    SetAmpVolumeResponse(bool),
-    SetPwmLightResponse,
-    SetGpioLightsResponse,
-    FinalizeResponse,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub enum ParentToChild {
-    InitRequest,
-    WriteOutputRequest,
-    ReadInputRequest,
-    GetInputGpioSysRequest,
-    GetInputGpioRequest(u8),
-    GetSpinnerPosRequest(u8),
-    SetAmpVolumeRequest {
-        primary: u8,
-        headphone: u8,
-        subwoofer: u8,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum ChildToParent<A: Api> {
+    HelloResponse(Hello),
+    Return(A::Return),
+    Response(A::Response),
+    /// Sent unprompted with id `0`, such as streamed input.
+    Push(A::Push),
+    SetLogLevelResponse,
+    /// Sent unprompted with id `0` for each log record, once the parent set a log level.
+    Log {
+        level: LogLevel,
+        target: String,
+        message: String,
     },
-    SetPwmLightRequest {
-        light_no: u8,
-        intensity: u8,
+    /// Answers a request the child could not serve, instead of its usual response.
+    Error {
+        request_id: u32,
+        kind: ChildErrorKind,
+        message: String,
     },
-    SetGpioLightsRequest(u32),
-    FinalizeRequest,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChildErrorKind {
+    /// The wrapped library, or the child while handling the request, panicked.
+    Panicked,
+    /// The request failed for the reason given in the message.
+    Failed,
+}
+
+/// Longest error message sent by the child, longer ones are truncated.
+pub const MAX_ERROR_MESSAGE: usize = 900;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum ParentToChild<A: Api> {
+    Hello(Hello),
+    /// Calls a function of the wrapped library, answered with the matching `Return`.
+    Call(A::Call),
+    /// Answered with a `Response`.
+    Request(A::Request),
+    /// Forwards the child's log records up to this level, or stops forwarding them.
+    SetLogLevel(Option<LogLevel>),
+}
+
+impl<A: Api> ChildToParent<A> {
+    /// Builds an `Error` answering `request_id`, truncating the message so that it always fits.
+    pub fn error(request_id: u32, kind: ChildErrorKind, message: &str) -> Self {
+        ChildToParent::Error {
+            request_id,
+            kind,
+            message: truncate(message, MAX_ERROR_MESSAGE).to_owned(),
+        }
+    }
+}
+
+/// Id of messages that do not answer a request.
+pub const PUSH_ID: u32 = 0;
+
 impl<T> Message<T> {
     pub fn new(payload: T) -> Self {
         static NEXT_ID: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(1);
@@ -56,6 +103,10 @@ impl<T> Message<T> {
         Self { id, payload }
     }
 
+    pub fn push(payload: T) -> Self {
+        Self::with_id(PUSH_ID, payload)
+    }
+
     pub fn reply<U>(&self, payload: U) -> Message<U> {
         Message {
             id: self.id,
@@ -0,0 +1,63 @@
+use crate::{Api, ChildToParent};
+use serde::{Deserialize, Serialize};
+
+/// Longest log target forwarded by the child, longer ones are truncated.
+pub const MAX_LOG_TARGET: usize = 64;
+/// Longest log message forwarded by the child, longer ones are truncated.
+pub const MAX_LOG_MESSAGE: usize = 900;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<log::Level> for LogLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => LogLevel::Error,
+            log::Level::Warn => LogLevel::Warn,
+            log::Level::Info => LogLevel::Info,
+            log::Level::Debug => LogLevel::Debug,
+            log::Level::Trace => LogLevel::Trace,
+        }
+    }
+}
+
+impl From<LogLevel> for log::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => log::Level::Error,
+            LogLevel::Warn => log::Level::Warn,
+            LogLevel::Info => log::Level::Info,
+            LogLevel::Debug => log::Level::Debug,
+            LogLevel::Trace => log::Level::Trace,
+        }
+    }
+}
+
+impl<A: Api> ChildToParent<A> {
+    /// Builds a `Log` message, truncating the target and message so that it always fits.
+    pub fn log(level: LogLevel, target: &str, message: &str) -> Self {
+        ChildToParent::Log {
+            level,
+            target: truncate(target, MAX_LOG_TARGET).to_owned(),
+            message: truncate(message, MAX_LOG_MESSAGE).to_owned(),
+        }
+    }
+}
+
+pub(crate) fn truncate(value: &str, max: usize) -> &str {
+    if value.len() <= max {
+        return value;
+    }
+    let end = (0..=max)
+        .rev()
+        .find(|&end| value.is_char_boundary(end))
+        .unwrap_or(0);
+    &value[..end]
+}
@@ -0,0 +1,278 @@
+use crate::Arch;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+const MACHINE_I386: u16 = 0x014c;
+const MACHINE_AMD64: u16 = 0x8664;
+const MACHINE_ARM64: u16 = 0xaa64;
+
+const EXPORT_DIRECTORY: usize = 0;
+const IMPORT_DIRECTORY: usize = 1;
+
+/// The parts of a PE image needed to check a library without loading it.
+#[derive(Debug, Clone)]
+pub struct PeImage {
+    pub machine: u16,
+    /// Names of the exported functions.
+    pub exports: Vec<String>,
+    /// Names of the libraries loaded along with the image.
+    pub imports: Vec<String>,
+}
+
+struct Section {
+    virtual_address: u32,
+    virtual_size: u32,
+    raw_offset: u32,
+    raw_size: u32,
+}
+
+impl PeImage {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> std::io::Result<Self> {
+        if data.get(..2) != Some(b"MZ") {
+            return Err(invalid("missing DOS header"));
+        }
+        let pe = u32_at(data, 0x3c)? as usize;
+        if bytes_at(data, pe).ok() != Some(*b"PE\0\0") {
+            return Err(invalid("missing PE signature"));
+        }
+
+        let coff = pe + 4;
+        let machine = u16_at(data, coff)?;
+        let section_count = u16_at(data, coff + 2)? as usize;
+        let optional_size = u16_at(data, coff + 16)? as usize;
+
+        let optional = coff + 20;
+        let directories = match u16_at(data, optional)? {
+            0x10b => optional + 92,
+            0x20b => optional + 108,
+            magic => {
+                return Err(invalid(format!(
+                    "unknown optional header magic {:#x}",
+                    magic
+                )));
+            }
+        };
+        let directory_count = u32_at(data, directories)? as usize;
+        let directory = |index: usize| -> std::io::Result<Option<u32>> {
+            if index >= directory_count {
+                return Ok(None);
+            }
+            let rva = u32_at(data, directories + 4 + index * 8)?;
+            Ok((rva != 0).then_some(rva))
+        };
+
+        let sections = (0..section_count)
+            .map(|index| {
+                let header = optional + optional_size + index * 40;
+                Ok(Section {
+                    virtual_size: u32_at(data, header + 8)?,
+                    virtual_address: u32_at(data, header + 12)?,
+                    raw_size: u32_at(data, header + 16)?,
+                    raw_offset: u32_at(data, header + 20)?,
+                })
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let offset = |rva: u32| -> std::io::Result<usize> {
+            sections
+                .iter()
+                .find(|section| {
+                    let size = section.virtual_size.max(section.raw_size);
+                    rva >= section.virtual_address && rva - section.virtual_address < size
+                })
+                .and_then(|section| (rva - section.virtual_address).checked_add(section.raw_offset))
+                .map(|offset| offset as usize)
+                .ok_or_else(|| invalid(format!("address {:#x} is outside every section", rva)))
+        };
+
+        let mut exports = Vec::new();
+        if let Some(rva) = directory(EXPORT_DIRECTORY)? {
+            let table = offset(rva)?;
+            let name_count = u32_at(data, table + 24)? as usize;
+            let names = offset(u32_at(data, table + 32)?)?;
+            for index in 0..name_count {
+                let name = u32_at(data, names.saturating_add(index.saturating_mul(4)))?;
+                exports.push(string_at(data, offset(name)?)?);
+            }
+        }
+
+        let mut imports = Vec::new();
+        if let Some(rva) = directory(IMPORT_DIRECTORY)? {
+            let mut descriptor = offset(rva)?;
+            loop {
+                let name = u32_at(data, descriptor + 12)?;
+                if name == 0 {
+                    break;
+                }
+                imports.push(string_at(data, offset(name)?)?);
+                descriptor += 20;
+            }
+        }
+
+        Ok(Self {
+            machine,
+            exports,
+            imports,
+        })
+    }
+
+    /// Architecture the image was built for.
+    pub fn arch(&self) -> Arch {
+        match self.machine {
+            MACHINE_I386 => Arch::X86,
+            MACHINE_AMD64 => Arch::X86_64,
+            MACHINE_ARM64 => Arch::Aarch64,
+            _ => Arch::Unknown,
+        }
+    }
+}
+
+pub(crate) fn invalid(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+fn bytes_at<const N: usize>(data: &[u8], offset: usize) -> std::io::Result<[u8; N]> {
+    // Offsets come from the image, so they may point anywhere
+    data.get(offset..offset.saturating_add(N))
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid("truncated image"))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> std::io::Result<u16> {
+    bytes_at(data, offset).map(u16::from_le_bytes)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> std::io::Result<u32> {
+    bytes_at(data, offset).map(u32::from_le_bytes)
+}
+
+fn string_at(data: &[u8], offset: usize) -> std::io::Result<String> {
+    let bytes = data
+        .get(offset..)
+        .ok_or_else(|| invalid("truncated image"))?;
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| invalid("unterminated name"))?;
+    Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PE: usize = 0x40;
+    const SECTION_RVA: u32 = 0x1000;
+    const SECTION_OFFSET: usize = 0x200;
+    const EXPORT_NAMES: usize = 0x40;
+    const STRINGS: usize = 0x80;
+    const IMPORTS: usize = 0x100;
+    const IMPORT_NAMES: usize = 0x180;
+
+    fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn put_rva(data: &mut [u8], offset: usize, section_offset: usize) {
+        put(
+            data,
+            offset,
+            &(SECTION_RVA + section_offset as u32).to_le_bytes(),
+        );
+    }
+
+    /// A library of one section, exporting `sdvx_io_init` and `sdvx_io_fini`, and importing
+    /// `KERNEL32.dll` and `msvcrt.dll`.
+    fn library(machine: u16) -> Vec<u8> {
+        let mut data = vec![0; SECTION_OFFSET + 0x200];
+        put(&mut data, 0, b"MZ");
+        put(&mut data, 0x3c, &(PE as u32).to_le_bytes());
+        put(&mut data, PE, b"PE\0\0");
+
+        let coff = PE + 4;
+        let (magic, directories) = match machine {
+            MACHINE_I386 => (0x10b_u16, 92),
+            _ => (0x20b, 108),
+        };
+        let optional_size = directories + 4 + 2 * 8;
+        put(&mut data, coff, &machine.to_le_bytes());
+        put(&mut data, coff + 2, &1_u16.to_le_bytes());
+        put(&mut data, coff + 16, &(optional_size as u16).to_le_bytes());
+
+        let optional = coff + 20;
+        put(&mut data, optional, &magic.to_le_bytes());
+        put(&mut data, optional + directories, &2_u32.to_le_bytes());
+        put_rva(&mut data, optional + directories + 4, 0);
+        put_rva(&mut data, optional + directories + 12, IMPORTS);
+
+        let section = optional + optional_size;
+        put(&mut data, section, b".rdata\0\0");
+        put(&mut data, section + 8, &0x200_u32.to_le_bytes());
+        put(&mut data, section + 12, &SECTION_RVA.to_le_bytes());
+        put(&mut data, section + 16, &0x200_u32.to_le_bytes());
+        put(
+            &mut data,
+            section + 20,
+            &(SECTION_OFFSET as u32).to_le_bytes(),
+        );
+
+        let exports = SECTION_OFFSET;
+        put(&mut data, exports + 24, &2_u32.to_le_bytes());
+        put_rva(&mut data, exports + 32, EXPORT_NAMES);
+        put_rva(&mut data, exports + EXPORT_NAMES, STRINGS);
+        put_rva(&mut data, exports + EXPORT_NAMES + 4, STRINGS + 16);
+        put(&mut data, exports + STRINGS, b"sdvx_io_init\0");
+        put(&mut data, exports + STRINGS + 16, b"sdvx_io_fini\0");
+
+        put_rva(&mut data, exports + IMPORTS + 12, IMPORT_NAMES);
+        put_rva(&mut data, exports + IMPORTS + 32, IMPORT_NAMES + 16);
+        put(&mut data, exports + IMPORT_NAMES, b"KERNEL32.dll\0");
+        put(&mut data, exports + IMPORT_NAMES + 16, b"msvcrt.dll\0");
+        data
+    }
+
+    #[test]
+    fn reads_the_exports_and_imports() {
+        let image = PeImage::parse(&library(MACHINE_AMD64)).unwrap();
+        assert_eq!(image.exports, ["sdvx_io_init", "sdvx_io_fini"]);
+        assert_eq!(image.imports, ["KERNEL32.dll", "msvcrt.dll"]);
+    }
+
+    #[test]
+    fn reads_the_machine() {
+        let image = PeImage::parse(&library(MACHINE_I386)).unwrap();
+        assert_eq!(image.arch(), Arch::X86);
+        assert_eq!(image.exports.len(), 2);
+        let image = PeImage::parse(&library(MACHINE_AMD64)).unwrap();
+        assert_eq!(image.arch(), Arch::X86_64);
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let data = library(MACHINE_AMD64);
+        // The name of the last import is the last byte read
+        let end = SECTION_OFFSET + IMPORT_NAMES + 16 + b"msvcrt.dll".len();
+        assert!(PeImage::parse(&data[..=end]).is_ok());
+        for len in 0..=end {
+            assert!(PeImage::parse(&data[..len]).is_err(), "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn rejects_addresses_outside_the_sections() {
+        let mut data = library(MACHINE_AMD64);
+        put(&mut data, SECTION_OFFSET + 32, &0x9000_u32.to_le_bytes());
+        assert!(PeImage::parse(&data).is_err());
+
+        let mut data = library(MACHINE_AMD64);
+        put(
+            &mut data,
+            SECTION_OFFSET + IMPORTS + 12,
+            &u32::MAX.to_le_bytes(),
+        );
+        assert!(PeImage::parse(&data).is_err());
+    }
+}
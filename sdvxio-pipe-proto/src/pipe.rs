@@ -1,52 +1,304 @@
-use std::io::{Read, Write};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::{Arc, Mutex};
+
+/// Upper bound of a serialized message. Buffers grow with the messages, this only keeps a
+/// receiver from buffering garbage without delimiters forever.
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Frames hold a serialized message followed by its CRC, COBS encoded.
+const MAX_FRAME_SIZE: usize = cobs::max_encoding_length(MAX_MESSAGE_SIZE + CRC_SIZE);
+const CRC_SIZE: usize = 4;
+const CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// Separates frames. COBS encoding removes it from the frames themselves, so that a receiver can
+/// always find the start of the next frame.
+const DELIMITER: u8 = 0;
 
 pub struct Sender<W: Write, T> {
     ipc: W,
+    /// Kept between messages so that sending does not allocate once it reached their size.
+    payload: Vec<u8>,
+    frame: Vec<u8>,
     phantom: std::marker::PhantomData<T>,
 }
 
 pub struct Receiver<R: Read, T> {
-    ipc: R,
+    ipc: BufReader<R>,
+    frame: Vec<u8>,
     phantom: std::marker::PhantomData<T>,
 }
 
+/// Writer shared between several senders. Each write is done whole under a lock, so messages
+/// sent from different threads never interleave.
+pub struct SharedWriter<W: Write> {
+    ipc: Arc<Mutex<W>>,
+}
+
+/// A frame that failed its checksum or could not be decoded, and was skipped.
+///
+/// Returned by [`Receiver::recv`] as the inner error of an [`std::io::ErrorKind::InvalidData`]
+/// error. The receiver is ready to read the next frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CorruptFrame {
+    /// Bytes thrown away, including the delimiter.
+    pub discarded: usize,
+}
+
 impl<W: Write, T: serde::Serialize> Sender<W, T> {
     pub fn new(ipc: W) -> Self {
         Self {
             ipc,
+            payload: Vec::new(),
+            frame: Vec::new(),
             phantom: std::marker::PhantomData,
         }
     }
 
     pub fn send(&mut self, msg: &T) -> std::io::Result<()> {
-        let data = postcard::to_vec::<_, 16>(msg).map_err(|err| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Serialization error: {}", err),
-            )
-        })?;
-        self.ipc.write(data.as_slice())?;
+        let len = encode(msg, &mut self.payload, &mut self.frame)?;
+        self.ipc.write_all(&self.frame[..len])?;
         self.ipc.flush()?;
         Ok(())
     }
 }
 
+/// Encodes `msg` into a whole frame, delimiters included, to be written without a [`Sender`].
+pub fn encode_frame<T: serde::Serialize>(msg: &T) -> std::io::Result<Vec<u8>> {
+    let mut frame = Vec::new();
+    let len = encode(msg, &mut Vec::new(), &mut frame)?;
+    frame.truncate(len);
+    Ok(frame)
+}
+
+/// Serializes `msg` and its CRC into `payload`, then encodes them into `frame`, returning the
+/// length of the frame.
+fn encode<T: serde::Serialize>(
+    msg: &T,
+    payload: &mut Vec<u8>,
+    frame: &mut Vec<u8>,
+) -> std::io::Result<usize> {
+    payload.clear();
+    *payload = postcard::to_extend(msg, std::mem::take(payload))
+        .map_err(|err| std::io::Error::other(format!("Serialization error: {}", err)))?;
+    let len = payload.len();
+    if len > MAX_MESSAGE_SIZE {
+        return Err(std::io::Error::other(format!(
+            "Message of {} bytes exceeds the limit of {}",
+            len, MAX_MESSAGE_SIZE
+        )));
+    }
+    let crc = CRC.checksum(payload);
+    payload.extend_from_slice(&crc.to_le_bytes());
+
+    // The leading delimiter ends any garbage written before, so that it cannot spoil this frame
+    frame.clear();
+    frame.resize(cobs::max_encoding_length(len + CRC_SIZE) + 2, DELIMITER);
+    let encoded = cobs::encode(payload, &mut frame[1..]);
+    frame[encoded + 1] = DELIMITER;
+    Ok(encoded + 2)
+}
+
 impl<R: Read, T: serde::de::DeserializeOwned> Receiver<R, T> {
     pub fn new(ipc: R) -> Self {
         Self {
-            ipc,
+            ipc: BufReader::new(ipc),
+            frame: Vec::new(),
             phantom: std::marker::PhantomData,
         }
     }
 
+    /// Reads the next message. A corrupted frame is skipped and reported as a [`CorruptFrame`].
     pub fn recv(&mut self) -> std::io::Result<T> {
-        let mut buffer = [0u8; 16];
-        let (msg, _): (T, _) = postcard::from_io((&mut self.ipc, &mut buffer)).map_err(|err| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Deserialization error: {}", err),
-            )
-        })?;
-        Ok(msg)
+        loop {
+            let overflow = self.read_frame()?;
+            if self.frame.is_empty() && overflow == 0 {
+                continue;
+            }
+            if overflow == 0
+                && let Some(msg) = self.decode()
+            {
+                return Ok(msg);
+            }
+            return Err(CorruptFrame {
+                discarded: self.frame.len() + overflow + 1,
+            }
+            .into());
+        }
+    }
+
+    /// Reads up to the next delimiter into `frame`, returning how many bytes did not fit.
+    fn read_frame(&mut self) -> std::io::Result<usize> {
+        self.frame.clear();
+        let mut overflow = 0;
+        loop {
+            let available = self.ipc.fill_buf()?;
+            if available.is_empty() {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+
+            let (chunk, done) = match available.iter().position(|&byte| byte == DELIMITER) {
+                Some(end) => (&available[..end], true),
+                None => (available, false),
+            };
+            let fits = chunk.len().min(MAX_FRAME_SIZE - self.frame.len());
+            self.frame.extend_from_slice(&chunk[..fits]);
+            overflow += chunk.len() - fits;
+
+            let consumed = chunk.len() + done as usize;
+            self.ipc.consume(consumed);
+            if done {
+                return Ok(overflow);
+            }
+        }
+    }
+
+    fn decode(&mut self) -> Option<T> {
+        let len = cobs::decode_in_place(&mut self.frame).ok()?;
+        let (payload, crc) = self.frame[..len].split_at_checked(len.checked_sub(CRC_SIZE)?)?;
+        if CRC.checksum(payload).to_le_bytes() != crc {
+            return None;
+        }
+        postcard::from_bytes(payload).ok()
+    }
+}
+
+impl CorruptFrame {
+    /// Returns the corrupted frame behind `err`, if any.
+    pub fn from_io(err: &std::io::Error) -> Option<Self> {
+        err.get_ref()?.downcast_ref::<Self>().copied()
+    }
+}
+
+impl fmt::Display for CorruptFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Corrupted frame, {} bytes discarded", self.discarded)
+    }
+}
+
+impl std::error::Error for CorruptFrame {}
+
+impl From<CorruptFrame> for std::io::Error {
+    fn from(frame: CorruptFrame) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, frame)
+    }
+}
+
+impl<W: Write> SharedWriter<W> {
+    pub fn new(ipc: W) -> Self {
+        Self {
+            ipc: Arc::new(Mutex::new(ipc)),
+        }
+    }
+}
+
+impl<W: Write> Clone for SharedWriter<W> {
+    fn clone(&self) -> Self {
+        Self {
+            ipc: self.ipc.clone(),
+        }
+    }
+}
+
+impl<W: Write> Write for SharedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut ipc = self
+            .ipc
+            .lock()
+            .map_err(|_| std::io::Error::other("writer poisoned"))?;
+        ipc.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let mut ipc = self
+            .ipc
+            .lock()
+            .map_err(|_| std::io::Error::other("writer poisoned"))?;
+        ipc.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receiver(data: Vec<u8>) -> Receiver<std::io::Cursor<Vec<u8>>, Vec<u8>> {
+        Receiver::new(std::io::Cursor::new(data))
+    }
+
+    fn corrupt_frame(result: std::io::Result<Vec<u8>>) -> CorruptFrame {
+        CorruptFrame::from_io(&result.unwrap_err()).unwrap()
+    }
+
+    #[test]
+    fn round_trips_the_largest_message() {
+        // The length prefix of the vector takes 3 bytes
+        let msg = vec![0xa5; MAX_MESSAGE_SIZE - 3];
+        assert_eq!(postcard::to_allocvec(&msg).unwrap().len(), MAX_MESSAGE_SIZE);
+
+        let mut sender = Sender::new(Vec::new());
+        sender.send(&msg).unwrap();
+        sender.send(&vec![1, 2, 3]).unwrap();
+        let mut receiver = receiver(sender.ipc);
+        assert_eq!(receiver.recv().unwrap(), msg);
+        assert_eq!(receiver.recv().unwrap(), vec![1, 2, 3]);
+        assert_eq!(
+            receiver.recv().unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn skips_a_frame_failing_its_crc() {
+        let mut data = encode_frame(&vec![1u8, 2, 3, 4]).unwrap();
+        let corrupted = data.len();
+        // Past the leading delimiter and the COBS code, in the serialized message
+        data[3] ^= 0x80;
+        data.extend(encode_frame(&vec![5u8, 6]).unwrap());
+
+        let mut receiver = receiver(data);
+        // The leading delimiter is not part of the discarded frame
+        assert_eq!(
+            corrupt_frame(receiver.recv()),
+            CorruptFrame {
+                discarded: corrupted - 1
+            }
+        );
+        assert_eq!(receiver.recv().unwrap(), vec![5, 6]);
+    }
+
+    #[test]
+    fn skips_stray_bytes_before_a_frame() {
+        let mut data = b"stray".to_vec();
+        data.extend(encode_frame(&vec![7u8]).unwrap());
+
+        let mut receiver = receiver(data);
+        assert_eq!(
+            corrupt_frame(receiver.recv()),
+            CorruptFrame { discarded: 6 }
+        );
+        assert_eq!(receiver.recv().unwrap(), vec![7]);
+    }
+
+    #[test]
+    fn rejects_oversized_messages() {
+        let msg = vec![0xa5; MAX_MESSAGE_SIZE - 2];
+        let err = Sender::new(Vec::new()).send(&msg).unwrap_err();
+        assert!(err.to_string().contains("exceeds the limit"));
+        assert!(encode_frame(&msg).is_err());
+    }
+
+    #[test]
+    fn skips_oversized_frames() {
+        let mut data = vec![DELIMITER];
+        data.resize(MAX_FRAME_SIZE + 11, 0xa5);
+        data.extend(encode_frame(&vec![8u8]).unwrap());
+
+        let mut receiver = receiver(data);
+        let frame = corrupt_frame(receiver.recv());
+        assert_eq!(frame.discarded, MAX_FRAME_SIZE + 11);
+        assert!(receiver.frame.capacity() < MAX_FRAME_SIZE * 2);
+        assert_eq!(receiver.recv().unwrap(), vec![8]);
     }
 }
@@ -0,0 +1,151 @@
+//! The `sdvxio` API of Sound Voltex.
+
+use crate::api::define_calls;
+use crate::{Api, Capabilities};
+use serde::{Deserialize, Serialize};
+
+mod state;
+pub use state::*;
+
+/// Invokes `$callback!` with the `sdvxio.h` functions forwarded to the child, so that the proxy
+/// exports, the protocol messages and the program's dispatch are all generated from this list.
+/// Arguments given after the callback are passed first, followed by a comma.
+///
+/// `sdvx_io_set_loggers` is not forwarded, and `sdvx_io_init` is given without the game's thread
+/// functions, which only make sense in the game's process.
+#[macro_export]
+macro_rules! sdvxio_api {
+    ($callback:ident $(, $($args:tt)*)?) => {
+        $callback! {
+            $($($args)*,)?
+            lifecycle {
+                Init = sdvx_io_init as init() -> bool;
+                Fini = sdvx_io_fini as fini() -> ();
+            }
+            input {
+                ReadInput = sdvx_io_read_input as read_input() -> bool;
+                GetInputGpioSys = sdvx_io_get_input_gpio_sys as get_input_gpio_sys() -> u8;
+                GetInputGpio = sdvx_io_get_input_gpio as get_input_gpio(gpio_bank: u8) -> u16;
+                GetSpinnerPos = sdvx_io_get_spinner_pos as get_spinner_pos(spinner_no: u8) -> u16;
+            }
+            output {
+                SetGpioLights = sdvx_io_set_gpio_lights as set_gpio_lights(gpio_lights: u32) -> ();
+                SetPwmLight = sdvx_io_set_pwm_light
+                    as set_pwm_light(light_no: u8, intensity: u8) -> ();
+                WriteOutput = sdvx_io_write_output as write_output() -> bool;
+                SetAmpVolume = sdvx_io_set_amp_volume
+                    as set_amp_volume(primary: u8, headphone: u8, subwoofer: u8) -> bool;
+            }
+        }
+    };
+}
+
+crate::sdvxio_api!(define_calls);
+
+/// The `sdvxio` API.
+#[derive(Debug, Clone, Copy)]
+pub struct Sdvxio;
+
+impl Api for Sdvxio {
+    const NAME: &'static str = "sdvxio";
+    const CAPABILITIES: Capabilities = Capabilities::from_bits(
+        Capabilities::INPUT_SNAPSHOT.bits()
+            | Capabilities::OUTPUT_FRAME.bits()
+            | Capabilities::INPUT_STREAM.bits()
+            | Capabilities::AMP_VOLUME.bits(),
+    );
+    const INIT: Call = Call::Init {};
+    const FINI: Call = Call::Fini {};
+
+    type Call = Call;
+    type Return = Return;
+    type Request = Request;
+    type Response = Response;
+    type Push = StreamedInput;
+
+    fn initialized(value: &Return) -> bool {
+        *value == Return::Init(true)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Request {
+    /// Reads input and returns the state of every input getter at once.
+    ReadInputSnapshot,
+    /// Applies the light changes of a frame, then writes output.
+    WriteOutputFrame(OutputFrame),
+    /// Makes the child poll input by itself and push changes.
+    StartInputStream { rate_hz: u16 },
+}
+
+/// Answers the [`Request`] of the same name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Response {
+    ReadInputSnapshot {
+        success: bool,
+        snapshot: InputSnapshot,
+    },
+    WriteOutputFrame(bool),
+    StartInputStream(bool),
+}
+
+/// Values of the input getters right after a `sdvx_io_read_input` call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputSnapshot {
+    pub gpio_sys: u8,
+    pub gpio: [u16; 2],
+    pub spinners: [u16; 2],
+}
+
+/// Lights changed since the previous frame, as `(light_no, intensity)` pairs for PWM lights.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputFrame {
+    pub gpio_lights: Option<u32>,
+    pub pwm_lights: Vec<(u8, u8)>,
+}
+
+pub const PWM_LIGHT_COUNT: usize = 18;
+
+/// State of every light, `None` until the game sets it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightState {
+    pub gpio_lights: Option<u32>,
+    pub pwm_lights: [Option<u8>; PWM_LIGHT_COUNT],
+}
+
+impl LightState {
+    pub fn set(&mut self, frame: &OutputFrame) {
+        if let Some(lights) = frame.gpio_lights {
+            self.gpio_lights = Some(lights);
+        }
+        for &(light_no, intensity) in &frame.pwm_lights {
+            if let Some(light) = self.pwm_lights.get_mut(light_no as usize) {
+                *light = Some(intensity);
+            }
+        }
+    }
+
+    /// Returns the lights that differ from `previous`.
+    pub fn changes_since(&self, previous: &LightState) -> OutputFrame {
+        let gpio_lights = self
+            .gpio_lights
+            .filter(|_| self.gpio_lights != previous.gpio_lights);
+        let pwm_lights = (0..PWM_LIGHT_COUNT)
+            .filter(|&light_no| self.pwm_lights[light_no] != previous.pwm_lights[light_no])
+            .filter_map(|light_no| Some((light_no as u8, self.pwm_lights[light_no]?)))
+            .collect();
+        OutputFrame {
+            gpio_lights,
+            pwm_lights,
+        }
+    }
+}
+
+/// Input streamed by the child, pushed or published through shared memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamedInput {
+    /// Incremented on every poll, so that readers can tell the stream is alive.
+    pub sequence: u32,
+    pub success: bool,
+    pub snapshot: InputSnapshot,
+}
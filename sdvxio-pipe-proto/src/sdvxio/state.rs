@@ -0,0 +1,225 @@
+use super::{InputSnapshot, LightState, PWM_LIGHT_COUNT};
+
+bitflags::bitflags! {
+    /// Bits of `sdvx_io_get_input_gpio_sys`.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub struct SysButtons: u8 {
+        const COIN = 1 << 2;
+        const SERVICE = 1 << 4;
+        const TEST = 1 << 5;
+    }
+}
+
+bitflags::bitflags! {
+    /// Bits of both `sdvx_io_get_input_gpio` banks, bank 0 in the low half and bank 1 in the
+    /// high half.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub struct GameButtons: u32 {
+        const C = 1 << 0;
+        const B = 1 << 1;
+        const A = 1 << 2;
+        const START = 1 << 3;
+        const RECORDER = 1 << 4;
+        const HEADPHONE = 1 << 5;
+        const FX_R = 1 << (16 + 3);
+        const FX_L = 1 << (16 + 4);
+        const D = 1 << (16 + 5);
+    }
+}
+
+impl GameButtons {
+    pub fn from_gpio(gpio: [u16; 2]) -> Self {
+        Self::from_bits_retain(gpio[0] as u32 | (gpio[1] as u32) << 16)
+    }
+
+    /// Splits the buttons back into the two banks.
+    pub fn to_gpio(self) -> [u16; 2] {
+        [self.bits() as u16, (self.bits() >> 16) as u16]
+    }
+}
+
+bitflags::bitflags! {
+    /// Bits of `sdvx_io_set_gpio_lights`.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub struct GpioLights: u32 {
+        const D = 1 << 0;
+        const FX_L = 1 << 1;
+        const FX_R = 1 << 2;
+        const GENERATOR_B = 1 << 3;
+        const START = 1 << 12;
+        const A = 1 << 13;
+        const B = 1 << 14;
+        const C = 1 << 15;
+    }
+}
+
+/// Position of a knob, counting up to 1023 and wrapping around.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SpinnerPos(u16);
+
+impl SpinnerPos {
+    pub const BITS: u32 = 10;
+    pub const RANGE: u16 = 1 << Self::BITS;
+
+    /// Keeps the low 10 bits of `raw`, as the game does.
+    pub fn new(raw: u16) -> Self {
+        Self(raw % Self::RANGE)
+    }
+
+    pub fn get(self) -> u16 {
+        self.0
+    }
+
+    /// Signed movement from `previous`, taking the shortest way around, so that turning across
+    /// the wrap is a small step rather than almost a full turn.
+    pub fn delta(self, previous: Self) -> i16 {
+        let forward = self.0.wrapping_sub(previous.0) % Self::RANGE;
+        if forward >= Self::RANGE / 2 {
+            forward as i16 - Self::RANGE as i16
+        } else {
+            forward as i16
+        }
+    }
+
+    /// Moves by `delta`, wrapping around.
+    pub fn wrapping_add(self, delta: i16) -> Self {
+        let position = (self.0 as i32 + delta as i32).rem_euclid(Self::RANGE as i32);
+        Self(position as u16)
+    }
+}
+
+/// One of the six groups of three PWM lights, as `(red, green, blue)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RgbGroup(u8);
+
+pub const RGB_GROUP_COUNT: usize = PWM_LIGHT_COUNT / 3;
+
+impl RgbGroup {
+    pub fn new(index: u8) -> Option<Self> {
+        ((index as usize) < RGB_GROUP_COUNT).then_some(Self(index))
+    }
+
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..RGB_GROUP_COUNT as u8).map(Self)
+    }
+
+    /// Group a PWM light belongs to.
+    pub fn of(light_no: u8) -> Option<Self> {
+        Self::new(light_no / 3)
+    }
+
+    pub fn index(self) -> u8 {
+        self.0
+    }
+
+    /// `light_no` of the red, green and blue lights.
+    pub fn lights(self) -> [u8; 3] {
+        let red = self.0 * 3;
+        [red, red + 1, red + 2]
+    }
+}
+
+/// Intensities of the lights of an [`RgbGroup`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+/// Attenuation of an amplifier, from 0 for the loudest to 96 for the quietest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AmpVolume(u8);
+
+impl AmpVolume {
+    pub const LOUDEST: Self = Self(0);
+    pub const QUIETEST: Self = Self(96);
+
+    /// Returns `None` if `raw` is out of range.
+    pub fn new(raw: u8) -> Option<Self> {
+        (raw <= Self::QUIETEST.0).then_some(Self(raw))
+    }
+
+    /// Clamps `raw` to the range.
+    pub fn saturating(raw: u8) -> Self {
+        Self(raw.min(Self::QUIETEST.0))
+    }
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl InputSnapshot {
+    pub fn sys_buttons(&self) -> SysButtons {
+        SysButtons::from_bits_retain(self.gpio_sys)
+    }
+
+    pub fn game_buttons(&self) -> GameButtons {
+        GameButtons::from_gpio(self.gpio)
+    }
+
+    pub fn spinners(&self) -> [SpinnerPos; 2] {
+        self.spinners.map(SpinnerPos::new)
+    }
+}
+
+impl LightState {
+    pub fn gpio_lights(&self) -> Option<GpioLights> {
+        self.gpio_lights.map(GpioLights::from_bits_retain)
+    }
+
+    /// Intensities of a group, treating lights the game has not set as off.
+    pub fn rgb(&self, group: RgbGroup) -> Rgb {
+        let [red, green, blue] = group
+            .lights()
+            .map(|light_no| self.pwm_lights[light_no as usize].unwrap_or(0));
+        Rgb { red, green, blue }
+    }
+
+    pub fn set_rgb(&mut self, group: RgbGroup, rgb: Rgb) {
+        let [red, green, blue] = group.lights();
+        self.pwm_lights[red as usize] = Some(rgb.red);
+        self.pwm_lights[green as usize] = Some(rgb.green);
+        self.pwm_lights[blue as usize] = Some(rgb.blue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spinner_delta_crosses_the_wrap() {
+        let (low, high) = (SpinnerPos::new(0), SpinnerPos::new(1023));
+        assert_eq!(low.delta(high), 1);
+        assert_eq!(high.delta(low), -1);
+        assert_eq!(SpinnerPos::new(5).delta(SpinnerPos::new(1020)), 9);
+        assert_eq!(SpinnerPos::new(1020).delta(SpinnerPos::new(5)), -9);
+        // Half a turn has no shortest way, and counts backwards
+        assert_eq!(SpinnerPos::new(612).delta(SpinnerPos::new(100)), -512);
+        assert_eq!(SpinnerPos::new(100).delta(SpinnerPos::new(612)), -512);
+    }
+
+    #[test]
+    fn spinner_wrapping_add_crosses_the_wrap() {
+        assert_eq!(SpinnerPos::new(1023).wrapping_add(1), SpinnerPos::new(0));
+        assert_eq!(SpinnerPos::new(0).wrapping_add(-1), SpinnerPos::new(1023));
+        assert_eq!(SpinnerPos::new(1020).wrapping_add(9), SpinnerPos::new(5));
+        assert_eq!(SpinnerPos::new(5).wrapping_add(-9), SpinnerPos::new(1020));
+        assert_eq!(SpinnerPos::new(3).wrapping_add(-2050), SpinnerPos::new(1));
+    }
+
+    #[test]
+    fn spinner_wrapping_add_undoes_delta() {
+        for previous in 0..SpinnerPos::RANGE {
+            let previous = SpinnerPos::new(previous);
+            for current in 0..SpinnerPos::RANGE {
+                let current = SpinnerPos::new(current);
+                let delta = current.delta(previous);
+                assert!((-512..512).contains(&delta));
+                assert_eq!(previous.wrapping_add(delta), current);
+            }
+        }
+    }
+}
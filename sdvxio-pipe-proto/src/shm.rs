@@ -0,0 +1,468 @@
+use memmap2::MmapMut;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering, fence};
+use std::time::{Duration, Instant};
+
+/// Shared by the transports of every API.
+const MAGIC: u32 = u32::from_le_bytes(*b"BT5P");
+const LAYOUT_VERSION: u32 = 1;
+const RING_SIZE: usize = 4096;
+const BLOCK_WORDS: usize = 8;
+
+/// Readers spin, then yield for this long, before falling back to sleeping between polls.
+const BUSY_WAIT: Duration = Duration::from_millis(10);
+
+/// Layout of the mapped file, shared between both processes.
+#[repr(C)]
+struct Layout {
+    magic: AtomicU32,
+    version: AtomicU32,
+    /// Latest input, written by the child.
+    input: StateBlock,
+    /// Latest output, written by the parent.
+    output: StateBlock,
+    /// Parent to child messages.
+    requests: Ring,
+    /// Child to parent messages.
+    responses: Ring,
+}
+
+/// Seqlock protected value, with a single writer and any number of readers.
+#[repr(C)]
+struct StateBlock {
+    seq: AtomicU32,
+    len: AtomicU32,
+    data: [AtomicU64; BLOCK_WORDS],
+}
+
+/// Single producer, single consumer byte ring.
+#[repr(C)]
+struct Ring {
+    head: AtomicU32,
+    tail: AtomicU32,
+    closed: AtomicU32,
+    data: [AtomicU8; RING_SIZE],
+}
+
+/// A memory-mapped file holding the input and output state blocks and the message rings.
+pub struct ShmRegion {
+    map: MmapMut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShmSide {
+    Parent,
+    Child,
+}
+
+/// Writes into the ring read by the other side.
+pub struct ShmWriter {
+    region: Arc<ShmRegion>,
+    side: ShmSide,
+}
+
+/// Reads from the ring written by the other side.
+pub struct ShmReader {
+    region: Arc<ShmRegion>,
+    side: ShmSide,
+}
+
+impl ShmRegion {
+    /// Creates, or resets, the mapped file at `path`.
+    pub fn create(path: &Path) -> std::io::Result<Arc<Self>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(size_of::<Layout>() as u64)?;
+        let region = Self::map(&file)?;
+        region
+            .layout()
+            .version
+            .store(LAYOUT_VERSION, Ordering::Relaxed);
+        region.layout().magic.store(MAGIC, Ordering::Release);
+        Ok(region)
+    }
+
+    /// Opens a mapped file previously created by [`ShmRegion::create`].
+    pub fn open(path: &Path) -> std::io::Result<Arc<Self>> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        if file.metadata()?.len() < size_of::<Layout>() as u64 {
+            return Err(std::io::Error::other("shared memory file is too small"));
+        }
+        let region = Self::map(&file)?;
+        let layout = region.layout();
+        if layout.magic.load(Ordering::Acquire) != MAGIC
+            || layout.version.load(Ordering::Relaxed) != LAYOUT_VERSION
+        {
+            return Err(std::io::Error::other(
+                "shared memory file has an unknown layout",
+            ));
+        }
+        Ok(region)
+    }
+
+    fn map(file: &File) -> std::io::Result<Arc<Self>> {
+        let map = unsafe { MmapMut::map_mut(file)? };
+        Ok(Arc::new(Self { map }))
+    }
+
+    fn layout(&self) -> &Layout {
+        // The mapping is page aligned, at least as large as `Layout`, and only ever accessed
+        // through atomics, for which every bit pattern is valid.
+        unsafe { &*(self.map.as_ptr() as *const Layout) }
+    }
+
+    pub fn writer(self: &Arc<Self>, side: ShmSide) -> ShmWriter {
+        ShmWriter {
+            region: self.clone(),
+            side,
+        }
+    }
+
+    pub fn reader(self: &Arc<Self>, side: ShmSide) -> ShmReader {
+        ShmReader {
+            region: self.clone(),
+            side,
+        }
+    }
+
+    pub fn publish_input<T: Serialize>(&self, value: &T) -> std::io::Result<()> {
+        self.layout().input.publish(value)
+    }
+
+    pub fn read_input<T: DeserializeOwned>(&self) -> Option<T> {
+        self.layout().input.read()
+    }
+
+    pub fn publish_output<T: Serialize>(&self, value: &T) -> std::io::Result<()> {
+        self.layout().output.publish(value)
+    }
+
+    pub fn read_output<T: DeserializeOwned>(&self) -> Option<T> {
+        self.layout().output.read()
+    }
+
+    /// Closes both rings, making pending and future reads return end of file.
+    pub fn close(&self) {
+        self.layout().requests.closed.store(1, Ordering::Release);
+        self.layout().responses.closed.store(1, Ordering::Release);
+    }
+}
+
+impl ShmSide {
+    fn outgoing(self, layout: &Layout) -> &Ring {
+        match self {
+            ShmSide::Parent => &layout.requests,
+            ShmSide::Child => &layout.responses,
+        }
+    }
+
+    fn incoming(self, layout: &Layout) -> &Ring {
+        match self {
+            ShmSide::Parent => &layout.responses,
+            ShmSide::Child => &layout.requests,
+        }
+    }
+}
+
+impl StateBlock {
+    fn publish<T: Serialize>(&self, value: &T) -> std::io::Result<()> {
+        let mut bytes = [0u8; BLOCK_WORDS * 8];
+        let len = postcard::to_slice(value, &mut bytes)
+            .map_err(|err| std::io::Error::other(format!("Serialization error: {}", err)))?
+            .len();
+
+        // Even, in case a previous writer died in the middle of publishing
+        let seq = self.seq.load(Ordering::Relaxed) & !1;
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.len.store(len as u32, Ordering::Relaxed);
+        for (word, chunk) in self.data.iter().zip(bytes.chunks_exact(8)) {
+            word.store(
+                u64::from_le_bytes(chunk.try_into().unwrap()),
+                Ordering::Relaxed,
+            );
+        }
+        // `0` means nothing was published yet, skip it when wrapping around
+        self.seq
+            .store(seq.wrapping_add(2).max(2), Ordering::Release);
+        Ok(())
+    }
+
+    /// Returns `None` until a value was published, or if the writer stalls in the middle of
+    /// publishing, as it does when its process dies there.
+    fn read<T: DeserializeOwned>(&self) -> Option<T> {
+        let mut bytes = [0u8; BLOCK_WORDS * 8];
+        let started = Instant::now();
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq == 0 {
+                return None;
+            }
+            if seq % 2 == 1 {
+                if started.elapsed() >= BUSY_WAIT {
+                    return None;
+                }
+                std::hint::spin_loop();
+                continue;
+            }
+
+            let len = self.len.load(Ordering::Relaxed) as usize;
+            for (word, chunk) in self.data.iter().zip(bytes.chunks_exact_mut(8)) {
+                chunk.copy_from_slice(&word.load(Ordering::Relaxed).to_le_bytes());
+            }
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return postcard::from_bytes(bytes.get(..len)?).ok();
+            }
+        }
+    }
+}
+
+impl Write for ShmWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let ring = self.side.outgoing(self.region.layout());
+        let mut waiter = Waiter::new();
+        loop {
+            if ring.closed.load(Ordering::Acquire) != 0 {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+
+            let head = ring.head.load(Ordering::Relaxed);
+            let tail = ring.tail.load(Ordering::Acquire);
+            let free = RING_SIZE - head.wrapping_sub(tail) as usize;
+            if free == 0 {
+                waiter.wait();
+                continue;
+            }
+
+            let len = buf.len().min(free);
+            for (i, byte) in buf[..len].iter().enumerate() {
+                let index = head.wrapping_add(i as u32) as usize % RING_SIZE;
+                ring.data[index].store(*byte, Ordering::Relaxed);
+            }
+            ring.head
+                .store(head.wrapping_add(len as u32), Ordering::Release);
+            return Ok(len);
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ShmWriter {
+    fn drop(&mut self) {
+        let ring = self.side.outgoing(self.region.layout());
+        ring.closed.store(1, Ordering::Release);
+    }
+}
+
+impl Read for ShmReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let ring = self.side.incoming(self.region.layout());
+        let mut waiter = Waiter::new();
+        loop {
+            let tail = ring.tail.load(Ordering::Relaxed);
+            let head = ring.head.load(Ordering::Acquire);
+            let available = head.wrapping_sub(tail) as usize;
+            if available == 0 {
+                if ring.closed.load(Ordering::Acquire) != 0 {
+                    return Ok(0);
+                }
+                waiter.wait();
+                continue;
+            }
+
+            let len = buf.len().min(available);
+            for (i, byte) in buf[..len].iter_mut().enumerate() {
+                let index = tail.wrapping_add(i as u32) as usize % RING_SIZE;
+                *byte = ring.data[index].load(Ordering::Relaxed);
+            }
+            ring.tail
+                .store(tail.wrapping_add(len as u32), Ordering::Release);
+            return Ok(len);
+        }
+    }
+}
+
+struct Waiter {
+    started: Instant,
+    spins: u32,
+}
+
+impl Waiter {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            spins: 0,
+        }
+    }
+
+    fn wait(&mut self) {
+        if self.spins < 100 {
+            self.spins += 1;
+            std::hint::spin_loop();
+        } else if self.started.elapsed() < BUSY_WAIT {
+            std::thread::yield_now();
+        } else {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Removes the mapped file once the test is done with it.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!(
+                "sdvxio-pipe-shm-{}-{}",
+                std::process::id(),
+                name
+            )))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn round_trips_through_the_rings() {
+        let file = TempFile::new("rings");
+        let parent = ShmRegion::create(&file.0).unwrap();
+        let child = ShmRegion::open(&file.0).unwrap();
+
+        let (mut writer, mut reader) =
+            (parent.writer(ShmSide::Parent), child.reader(ShmSide::Child));
+        // Three quarters of the ring at a time, so that the second write wraps around
+        for round in 0..3u8 {
+            let sent: Vec<u8> = (0..RING_SIZE * 3 / 4).map(|i| i as u8 ^ round).collect();
+            writer.write_all(&sent).unwrap();
+            let mut received = vec![0; sent.len()];
+            reader.read_exact(&mut received).unwrap();
+            assert_eq!(received, sent);
+        }
+
+        let (mut writer, mut reader) =
+            (child.writer(ShmSide::Child), parent.reader(ShmSide::Parent));
+        writer.write_all(b"response").unwrap();
+        let mut received = [0; 8];
+        reader.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"response");
+    }
+
+    #[test]
+    fn blocks_a_writer_until_the_ring_has_room() {
+        let file = TempFile::new("full");
+        let region = ShmRegion::create(&file.0).unwrap();
+        let sent: Vec<u8> = (0..RING_SIZE * 3).map(|i| (i % 251) as u8).collect();
+
+        let mut writer = region.writer(ShmSide::Parent);
+        let expected = sent.clone();
+        let writing = std::thread::spawn(move || writer.write_all(&sent));
+        let mut received = vec![0; expected.len()];
+        region
+            .reader(ShmSide::Child)
+            .read_exact(&mut received)
+            .unwrap();
+        writing.join().unwrap().unwrap();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn close_ends_both_rings() {
+        let file = TempFile::new("close");
+        let region = ShmRegion::create(&file.0).unwrap();
+        let mut writer = region.writer(ShmSide::Parent);
+        let mut reader = region.reader(ShmSide::Child);
+        writer.write_all(b"last").unwrap();
+        region.close();
+
+        // What was written before still arrives, then the end of file
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"last");
+        assert_eq!(
+            writer.write(b"more").unwrap_err().kind(),
+            std::io::ErrorKind::BrokenPipe
+        );
+        assert_eq!(region.reader(ShmSide::Parent).read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn dropping_a_writer_ends_its_ring() {
+        let file = TempFile::new("drop");
+        let region = ShmRegion::create(&file.0).unwrap();
+        drop(region.writer(ShmSide::Child));
+        assert_eq!(region.reader(ShmSide::Parent).read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn reads_the_latest_published_state() {
+        let file = TempFile::new("state");
+        let parent = ShmRegion::create(&file.0).unwrap();
+        let child = ShmRegion::open(&file.0).unwrap();
+        assert_eq!(parent.read_input::<[u16; 4]>(), None);
+        assert_eq!(child.read_output::<u32>(), None);
+
+        child.publish_input(&[1u16, 2, 3, 4]).unwrap();
+        child.publish_input(&[5u16, 6, 7, 8]).unwrap();
+        parent.publish_output(&0x1234u32).unwrap();
+        assert_eq!(parent.read_input(), Some([5u16, 6, 7, 8]));
+        assert_eq!(child.read_output(), Some(0x1234u32));
+        // The blocks are separate
+        assert_eq!(child.read_input(), Some([5u16, 6, 7, 8]));
+    }
+
+    #[test]
+    fn gives_up_on_a_stalled_writer() {
+        let file = TempFile::new("stalled");
+        let region = ShmRegion::create(&file.0).unwrap();
+        region.publish_input(&7u8).unwrap();
+        // As left by a writer dying in the middle of publishing
+        let input = &region.layout().input;
+        input.seq.fetch_add(1, Ordering::Relaxed);
+        assert_eq!(region.read_input::<u8>(), None);
+
+        region.publish_input(&8u8).unwrap();
+        assert_eq!(region.read_input(), Some(8u8));
+    }
+
+    #[test]
+    fn rejects_values_larger_than_a_block() {
+        let file = TempFile::new("large");
+        let region = ShmRegion::create(&file.0).unwrap();
+        assert!(
+            region
+                .publish_input(&vec![0xffu8; BLOCK_WORDS * 8])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rejects_files_of_another_layout() {
+        let file = TempFile::new("layout");
+        std::fs::write(&file.0, vec![0; size_of::<Layout>()]).unwrap();
+        assert!(ShmRegion::open(&file.0).is_err());
+        std::fs::write(&file.0, b"short").unwrap();
+        assert!(ShmRegion::open(&file.0).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use serde::Deserialize;
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// Address of a socket transport, written `tcp:<host>:<port>` or `unix:<path>`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Endpoint {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+/// A connected TCP or Unix domain socket.
+#[derive(Debug)]
+pub enum Socket {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Endpoint {
+    /// Connects to the endpoint, giving up after `timeout` if given.
+    pub fn connect(&self, timeout: Option<Duration>) -> std::io::Result<Socket> {
+        match self {
+            Endpoint::Tcp(address) => {
+                let mut last_error = None;
+                for address in address.to_socket_addrs()? {
+                    let stream = match timeout {
+                        Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+                        None => TcpStream::connect(address),
+                    };
+                    match stream {
+                        Ok(stream) => {
+                            stream.set_nodelay(true)?;
+                            return Ok(Socket::Tcp(stream));
+                        }
+                        Err(err) => last_error = Some(err),
+                    }
+                }
+                Err(last_error.unwrap_or_else(|| {
+                    std::io::Error::other(format!("{} did not resolve to any address", address))
+                }))
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => Ok(Socket::Unix(UnixStream::connect(path)?)),
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) => Err(unix_unsupported()),
+        }
+    }
+
+    /// Listens on the endpoint, replacing any stale Unix socket file.
+    pub fn bind(&self) -> std::io::Result<Listener> {
+        match self {
+            Endpoint::Tcp(address) => Ok(Listener::Tcp(TcpListener::bind(address.as_str())?)),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                if path.exists() {
+                    std::fs::remove_file(path)?;
+                }
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            }
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) => Err(unix_unsupported()),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn unix_unsupported() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Unix domain sockets are not supported on this platform",
+    )
+}
+
+impl FromStr for Endpoint {
+    type Err = std::io::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            Some(("tcp", address)) if !address.is_empty() => Ok(Endpoint::Tcp(address.to_owned())),
+            Some(("unix", path)) if !path.is_empty() => Ok(Endpoint::Unix(PathBuf::from(path))),
+            _ => Err(std::io::Error::other(format!(
+                "invalid endpoint {:?}, expected tcp:<host>:<port> or unix:<path>",
+                value
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for Endpoint {
+    type Error = std::io::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(address) => write!(f, "tcp:{}", address),
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl Socket {
+    pub fn try_clone(&self) -> std::io::Result<Self> {
+        match self {
+            Socket::Tcp(stream) => Ok(Socket::Tcp(stream.try_clone()?)),
+            #[cfg(unix)]
+            Socket::Unix(stream) => Ok(Socket::Unix(stream.try_clone()?)),
+        }
+    }
+
+    /// Closes both directions, making pending reads on every clone return end of file.
+    pub fn shutdown(&self) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.shutdown(Shutdown::Both),
+        }
+    }
+
+    /// Describes the other end, for logging.
+    pub fn peer(&self) -> String {
+        match self {
+            Socket::Tcp(stream) => stream
+                .peer_addr()
+                .map_or_else(|_| "unknown peer".to_owned(), |address| address.to_string()),
+            #[cfg(unix)]
+            Socket::Unix(_) => "local peer".to_owned(),
+        }
+    }
+
+    /// Makes reads fail after `timeout`, or block forever with `None`.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Socket::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Socket::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+impl Listener {
+    pub fn accept(&self) -> std::io::Result<Socket> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                Ok(Socket::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => Ok(Socket::Unix(listener.accept()?.0)),
+        }
+    }
+
+    /// Accepts a connection, giving up after `timeout`.
+    pub fn accept_timeout(&self, timeout: Duration) -> std::io::Result<Socket> {
+        self.set_nonblocking(true)?;
+        let started = Instant::now();
+        let socket = loop {
+            match self.accept() {
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    if started.elapsed() >= timeout {
+                        break Err(std::io::ErrorKind::TimedOut.into());
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                result => break result,
+            }
+        };
+        self.set_nonblocking(false)?;
+
+        let socket = socket?;
+        socket.set_nonblocking(false)?;
+        Ok(socket)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        match self {
+            Listener::Tcp(listener) => listener.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Receiver, Sender};
+
+    fn free_tcp_endpoint() -> Endpoint {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        Endpoint::Tcp(listener.local_addr().unwrap().to_string())
+    }
+
+    /// Sends a message each way between a listening and a connecting side.
+    fn round_trip(endpoint: &Endpoint) {
+        let listener = endpoint.bind().unwrap();
+        let connecting = endpoint.clone();
+        let client = std::thread::spawn(move || {
+            let socket = connecting.connect(Some(Duration::from_secs(5))).unwrap();
+            Sender::new(socket.try_clone().unwrap())
+                .send(&"hello".to_owned())
+                .unwrap();
+            Receiver::<_, u32>::new(socket).recv().unwrap()
+        });
+
+        let socket = listener.accept_timeout(Duration::from_secs(5)).unwrap();
+        let hello: String = Receiver::new(socket.try_clone().unwrap()).recv().unwrap();
+        assert_eq!(hello, "hello");
+        Sender::new(socket).send(&42u32).unwrap();
+        assert_eq!(client.join().unwrap(), 42);
+    }
+
+    #[test]
+    fn parses_and_displays_endpoints() {
+        for endpoint in [
+            "tcp:127.0.0.1:4000",
+            "tcp:[::1]:4000",
+            "unix:/run/sdvxio.sock",
+        ] {
+            assert_eq!(endpoint.parse::<Endpoint>().unwrap().to_string(), endpoint);
+        }
+        for endpoint in ["127.0.0.1:4000", "tcp:", "unix:", "udp:127.0.0.1:4000"] {
+            assert!(endpoint.parse::<Endpoint>().is_err(), "{endpoint}");
+        }
+    }
+
+    #[test]
+    fn round_trips_over_tcp() {
+        round_trip(&free_tcp_endpoint());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trips_over_a_unix_socket_replacing_a_stale_one() {
+        let path = std::env::temp_dir().join(format!("sdvxio-pipe-{}.sock", std::process::id()));
+        std::fs::write(&path, b"stale").unwrap();
+        round_trip(&Endpoint::Unix(path.clone()));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn gives_up_accepting_after_the_timeout() {
+        let listener = free_tcp_endpoint().bind().unwrap();
+        let err = listener
+            .accept_timeout(Duration::from_millis(20))
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    }
+}
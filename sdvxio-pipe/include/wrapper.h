@@ -1,2 +0,0 @@
-#include "bemanitools/glue.h"
-#include "bemanitools/sdvxio.h"
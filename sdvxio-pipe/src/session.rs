@@ -0,0 +1,341 @@
+use bt5_pipe::{Child, Error, Process, Session, TimeoutConfig};
+use sdvxio_pipe_proto::Capabilities;
+use sdvxio_pipe_proto::sdvxio::{
+    Call, InputSnapshot, LightState, OutputFrame, Request, Response, Return, Sdvxio,
+};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Pushed input older than this is considered stale, the child pushes at least every 100 ms.
+const STREAM_STALE_AFTER: Duration = Duration::from_millis(500);
+
+/// Input and output state kept in the parent, so that the input getters are served from a
+/// snapshot and lights are sent as frames when the child supports it.
+#[derive(Default)]
+pub struct SdvxioSession {
+    /// Input state taken at the last `sdvx_io_read_input`, if the child supports snapshots.
+    input: Option<InputSnapshot>,
+    /// Whether the child pushes input by itself.
+    input_streaming: bool,
+    outputs: OutputState,
+}
+
+/// Last output state set by the game, replayed after a respawn.
+#[derive(Default)]
+struct OutputState {
+    lights: LightState,
+    amp_volume: Option<(u8, u8, u8)>,
+    /// Lights as of the last output frame, so that only changed channels are sent.
+    sent: LightState,
+}
+
+/// Deadlines of the `sdvxio` functions in milliseconds, from `[timeouts.sdvxio]`, `0` waiting
+/// forever.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SdvxioTimeouts {
+    /// Applies to `sdvx_io_read_input` and input snapshots.
+    read_input_ms: u64,
+    /// Applies to the GPIO and spinner getters.
+    get_input_ms: u64,
+    /// Applies to `sdvx_io_write_output` and output frames.
+    write_output_ms: u64,
+    /// Applies to the GPIO and PWM light setters.
+    set_lights_ms: u64,
+    /// Applies to `sdvx_io_set_amp_volume`.
+    set_amp_volume_ms: u64,
+}
+
+impl Default for SdvxioTimeouts {
+    fn default() -> Self {
+        Self {
+            read_input_ms: 100,
+            get_input_ms: 100,
+            write_output_ms: 100,
+            set_lights_ms: 100,
+            set_amp_volume_ms: 1000,
+        }
+    }
+}
+
+impl Session for SdvxioSession {
+    type Api = Sdvxio;
+    type Timeouts = SdvxioTimeouts;
+
+    /// Serves a function from the input and output state kept here when the child supports it,
+    /// or forwards it.
+    fn call(child: &Child<Self>, call: Call) -> Result<Return, Error> {
+        let capabilities = child.capabilities();
+        let output_frame = capabilities.contains(Capabilities::OUTPUT_FRAME);
+        match call {
+            Call::SetGpioLights { .. } if output_frame => {
+                child.session().outputs.record(&call);
+                return Ok(Return::SetGpioLights(()));
+            }
+            Call::SetPwmLight { .. } if output_frame => {
+                child.session().outputs.record(&call);
+                return Ok(Return::SetPwmLight(()));
+            }
+            Call::WriteOutput {} if output_frame => {
+                return write_output_frame(child).map(Return::WriteOutput);
+            }
+            Call::ReadInput {} if child.session().input_streaming => {
+                return read_streamed_input(child).map(Return::ReadInput);
+            }
+            Call::ReadInput {} if capabilities.contains(Capabilities::INPUT_SNAPSHOT) => {
+                return read_input_snapshot(child).map(Return::ReadInput);
+            }
+            // The wrapped library may predate amp volume control
+            Call::SetAmpVolume { .. } if !capabilities.contains(Capabilities::AMP_VOLUME) => {
+                return Ok(Return::SetAmpVolume(false));
+            }
+            _ => {}
+        }
+        if let Some(value) = child
+            .session()
+            .input
+            .and_then(|input| from_snapshot(&input, call))
+        {
+            return Ok(value);
+        }
+
+        child.forward(call)
+    }
+
+    fn record(&mut self, call: &Call) {
+        self.outputs.record(call);
+    }
+
+    /// Starts input streaming if configured.
+    fn started(child: &Child<Self>, process: &Process<Sdvxio>) -> Result<(), Error> {
+        child.session().input_streaming = false;
+        let config = child.input_config();
+        if !config.streaming || !child.capabilities().contains(Capabilities::INPUT_STREAM) {
+            return Ok(());
+        }
+
+        let rate_hz = config.rate_hz;
+        match child.request_once(process, Request::StartInputStream { rate_hz }) {
+            Ok(Response::StartInputStream(started)) => {
+                child.session().input_streaming = started;
+                if !started {
+                    log::warn!("Child could not start input streaming, polling instead");
+                }
+            }
+            Err(Error::ChildFailed { message }) => {
+                log::warn!(
+                    "Child could not start input streaming, polling instead: {}",
+                    message
+                );
+            }
+            Ok(_) => return Err(Error::WrongResponseType),
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+
+    fn restore(child: &Child<Self>, process: &Process<Sdvxio>) -> Result<(), Error> {
+        let capabilities = child.capabilities();
+        let replay = child.session().outputs.replay(capabilities);
+        for call in replay {
+            child.call_once(process, call)?;
+        }
+        if capabilities.contains(Capabilities::OUTPUT_FRAME) {
+            let frame = {
+                let mut session = child.session();
+                session.outputs.sent = LightState::default();
+                output_frame(process, &mut session.outputs)?
+            };
+            child.request_once(process, Request::WriteOutputFrame(frame))?;
+        }
+        Ok(())
+    }
+
+    fn timeout(timeouts: &TimeoutConfig, api: &SdvxioTimeouts, call: &Call) -> u64 {
+        match call {
+            Call::ReadInput {} => api.read_input_ms,
+            Call::GetInputGpioSys {} | Call::GetInputGpio { .. } | Call::GetSpinnerPos { .. } => {
+                api.get_input_ms
+            }
+            Call::WriteOutput {} => api.write_output_ms,
+            Call::SetGpioLights { .. } | Call::SetPwmLight { .. } => api.set_lights_ms,
+            Call::SetAmpVolume { .. } => api.set_amp_volume_ms,
+            Call::Init {} | Call::Fini {} => timeouts.call::<Sdvxio>(call),
+        }
+    }
+
+    fn request_timeout(timeouts: &TimeoutConfig, api: &SdvxioTimeouts, request: &Request) -> u64 {
+        match request {
+            Request::ReadInputSnapshot => api.read_input_ms,
+            Request::WriteOutputFrame(_) => api.write_output_ms,
+            Request::StartInputStream { .. } => timeouts.init_ms,
+        }
+    }
+}
+
+/// Serves input from the latest push, falling back to a snapshot request if it is stale.
+fn read_streamed_input(child: &Child<SdvxioSession>) -> Result<bool, Error> {
+    match child.latest_push() {
+        Some((streamed, received)) if received.elapsed() < STREAM_STALE_AFTER => {
+            child.session().input = Some(streamed.snapshot);
+            Ok(streamed.success)
+        }
+        _ => read_input_snapshot(child),
+    }
+}
+
+/// Reads input and caches the state of every input getter.
+fn read_input_snapshot(child: &Child<SdvxioSession>) -> Result<bool, Error> {
+    match child.request(Request::ReadInputSnapshot)? {
+        Response::ReadInputSnapshot { success, snapshot } => {
+            child.session().input = Some(snapshot);
+            Ok(success)
+        }
+        _ => Err(Error::WrongResponseType),
+    }
+}
+
+/// Sends the lights changed since the previous frame and writes output.
+fn write_output_frame(child: &Child<SdvxioSession>) -> Result<bool, Error> {
+    let response = child.request_with(|process, session| {
+        Ok(Request::WriteOutputFrame(output_frame(
+            process,
+            &mut session.outputs,
+        )?))
+    })?;
+    match response {
+        Response::WriteOutputFrame(success) => Ok(success),
+        _ => Err(Error::WrongResponseType),
+    }
+}
+
+/// Builds the next output frame. With shared memory, the whole light state is published to the
+/// output block instead, and the frame only tells the child to apply it.
+fn output_frame(
+    process: &Process<Sdvxio>,
+    outputs: &mut OutputState,
+) -> Result<OutputFrame, Error> {
+    if process.publish_output(&outputs.lights)? {
+        outputs.sent = outputs.lights;
+        return Ok(OutputFrame::default());
+    }
+    Ok(outputs.take_frame())
+}
+
+/// Answers an input getter from the snapshot taken at the last `sdvx_io_read_input`.
+fn from_snapshot(snapshot: &InputSnapshot, call: Call) -> Option<Return> {
+    match call {
+        Call::GetInputGpioSys {} => Some(Return::GetInputGpioSys(snapshot.gpio_sys)),
+        Call::GetInputGpio { gpio_bank } => snapshot
+            .gpio
+            .get(gpio_bank as usize)
+            .map(|&gpio| Return::GetInputGpio(gpio)),
+        Call::GetSpinnerPos { spinner_no } => snapshot
+            .spinners
+            .get(spinner_no as usize)
+            .map(|&position| Return::GetSpinnerPos(position)),
+        _ => None,
+    }
+}
+
+impl OutputState {
+    fn record(&mut self, call: &Call) {
+        match *call {
+            Call::SetGpioLights { gpio_lights } => self.lights.gpio_lights = Some(gpio_lights),
+            Call::SetPwmLight {
+                light_no,
+                intensity,
+            } => {
+                if let Some(light) = self.lights.pwm_lights.get_mut(light_no as usize) {
+                    *light = Some(intensity);
+                }
+            }
+            Call::SetAmpVolume {
+                primary,
+                headphone,
+                subwoofer,
+            } => self.amp_volume = Some((primary, headphone, subwoofer)),
+            _ => {}
+        }
+    }
+
+    /// Returns the lights changed since the previous frame and marks them as sent.
+    fn take_frame(&mut self) -> OutputFrame {
+        let frame = self.lights.changes_since(&self.sent);
+        self.sent = self.lights;
+        frame
+    }
+
+    /// Calls restoring the amp volume if supported, and the lights unless they are sent as an
+    /// output frame, on a freshly spawned child.
+    fn replay(&self, capabilities: Capabilities) -> Vec<Call> {
+        let mut calls = Vec::new();
+        if let Some((primary, headphone, subwoofer)) = self
+            .amp_volume
+            .filter(|_| capabilities.contains(Capabilities::AMP_VOLUME))
+        {
+            calls.push(Call::SetAmpVolume {
+                primary,
+                headphone,
+                subwoofer,
+            });
+        }
+
+        if !capabilities.contains(Capabilities::OUTPUT_FRAME) {
+            if let Some(gpio_lights) = self.lights.gpio_lights {
+                calls.push(Call::SetGpioLights { gpio_lights });
+            }
+            for (light_no, intensity) in self.lights.pwm_lights.iter().enumerate() {
+                if let Some(intensity) = *intensity {
+                    calls.push(Call::SetPwmLight {
+                        light_no: light_no as u8,
+                        intensity,
+                    });
+                }
+            }
+            if !calls.is_empty() {
+                calls.push(Call::WriteOutput {});
+            }
+        }
+        calls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_pwm_light(light_no: u8, intensity: u8) -> Call {
+        Call::SetPwmLight {
+            light_no,
+            intensity,
+        }
+    }
+
+    #[test]
+    fn sends_only_the_lights_changed_since_the_last_frame() {
+        let mut outputs = OutputState::default();
+        outputs.record(&Call::SetGpioLights { gpio_lights: 0x5 });
+        outputs.record(&set_pwm_light(3, 200));
+        outputs.record(&set_pwm_light(4, 10));
+        assert_eq!(
+            outputs.take_frame(),
+            OutputFrame {
+                gpio_lights: Some(0x5),
+                pwm_lights: vec![(3, 200), (4, 10)],
+            }
+        );
+
+        outputs.record(&Call::SetGpioLights { gpio_lights: 0x5 });
+        outputs.record(&set_pwm_light(3, 200));
+        outputs.record(&set_pwm_light(4, 20));
+        assert_eq!(
+            outputs.take_frame(),
+            OutputFrame {
+                gpio_lights: None,
+                pwm_lights: vec![(4, 20)],
+            }
+        );
+        assert_eq!(outputs.take_frame(), OutputFrame::default());
+    }
+}